@@ -0,0 +1,66 @@
+use axum_csrf::{
+    test_util::{token_from_request, TestToken},
+    CsrfConfig,
+};
+use criterion::{criterion_group, criterion_main, Criterion};
+use http::{header::COOKIE, Request};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Counts allocations so the per request cost can be reported next to the timings.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn allocations<T>(f: impl FnOnce() -> T) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    black_box(f());
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn request(c: &mut Criterion) {
+    let config = CsrfConfig::default()
+        .with_cookie_domain(Some("example.com"))
+        .with_salt("a salt that is long enough to be realistic");
+    let test_token = TestToken::new(config.clone());
+    // Other cookies sent along with the CSRF cookie as a browser would.
+    let mut request = Request::post("/")
+        .header(
+            COOKIE,
+            "session=0123456789abcdef; theme=dark; _ga=GA1.2.345.678",
+        )
+        .body(())
+        .unwrap();
+    test_token.apply(&mut request);
+
+    // Warm up so lazily initialized state is not counted.
+    token_from_request(&request, config.clone());
+    let clone = allocations(|| config.clone());
+    let load = allocations(|| token_from_request(&request, config.clone()));
+    println!("allocations: config clone {clone}, token from request {load}");
+
+    c.bench_function("config_clone", |b| b.iter(|| config.clone()));
+    c.bench_function("token_from_request", |b| {
+        b.iter(|| token_from_request(black_box(&request), config.clone()))
+    });
+}
+
+criterion_group!(benches, request);
+criterion_main!(benches);
@@ -0,0 +1,94 @@
+use axum_csrf::{test_util::TestToken, CookieMode, CsrfConfig, HmacSha256, HmacSha512};
+use base64ct::{Base64, Encoding};
+use criterion::{criterion_group, criterion_main, Criterion};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::hint::black_box;
+use time::Duration;
+
+/// The verification path used before the keyed HMAC state was cached within the config.
+fn rekeyed_verify(salt: &str, token: &str, form_authenticity_token: &str) -> bool {
+    let mut mac = Hmac::<Sha256>::new_from_slice(salt.as_bytes()).unwrap();
+    mac.update(token.as_bytes());
+
+    let encoded = form_authenticity_token
+        .rsplit('.')
+        .next()
+        .unwrap_or_default();
+    let Ok(form_token) = Base64::decode_vec(encoded) else {
+        return false;
+    };
+
+    mac.verify_slice(&form_token).is_ok()
+}
+
+fn verify(c: &mut Criterion) {
+    let salt = "a salt that is long enough to be realistic";
+    // Without a lifespan the expiry check is skipped so only the hashing is measured.
+    // A plain cookie holds the Token itself, which the rekeyed path hashes.
+    let test_token = TestToken::new(
+        CsrfConfig::default()
+            .with_salt(salt)
+            .with_lifetime(Duration::ZERO)
+            .with_cookie_mode(CookieMode::Plain),
+    );
+    let token = test_token.token();
+    // Both paths hash the same cookie value against the same authenticity token.
+    let cookie_value = test_token
+        .cookie_header()
+        .to_str()
+        .unwrap()
+        .split_once('=')
+        .unwrap()
+        .1
+        .to_owned();
+    let authenticity_token = test_token.authenticity_token();
+    assert!(rekeyed_verify(salt, &cookie_value, authenticity_token));
+
+    let mut group = c.benchmark_group("verify");
+
+    group.bench_function("cached", |b| {
+        b.iter(|| token.verify(black_box(authenticity_token)))
+    });
+    group.bench_function("rekeyed", |b| {
+        b.iter(|| rekeyed_verify(salt, &cookie_value, black_box(authenticity_token)))
+    });
+    group.finish();
+}
+
+fn algorithms(c: &mut Criterion) {
+    let config = CsrfConfig::default().with_lifetime(Duration::ZERO);
+    let configs = [
+        ("hs256", config.clone().with_mac::<HmacSha256>()),
+        ("hs512", config.clone().with_mac::<HmacSha512>()),
+    ]
+    .into_iter();
+
+    #[cfg(feature = "blake3")]
+    let configs = configs.chain([("b3", config.with_mac::<axum_csrf::Blake3>())]);
+
+    let mut group = c.benchmark_group("algorithm");
+
+    for (name, config) in configs {
+        let test_token = TestToken::new(config);
+        let token = test_token.token();
+        let authenticity_token = test_token.authenticity_token();
+
+        group.bench_function(name, |b| {
+            b.iter(|| token.verify(black_box(authenticity_token)))
+        });
+    }
+
+    group.finish();
+}
+
+fn authenticity_token(c: &mut Criterion) {
+    let test_token = TestToken::new(CsrfConfig::default());
+
+    c.bench_function("authenticity_token", |b| {
+        b.iter(|| test_token.token().authenticity_token())
+    });
+}
+
+criterion_group!(benches, verify, algorithms, authenticity_token);
+criterion_main!(benches);
@@ -4,17 +4,22 @@ use axum::{
     extract::Request,
     http::{Method, StatusCode},
     middleware::Next,
-    response::{IntoResponse, Response},
+    response::{Html, IntoResponse, Response},
     routing::{get, post},
     Form, Router,
 };
-use axum_csrf::{CsrfConfig, CsrfLayer, CsrfToken, Key};
+use axum_csrf::{CsrfConfig, CsrfHiddenInput, CsrfLayer, CsrfToken, Key};
 use http_body_util::BodyExt;
 use serde::{Deserialize, Serialize};
 use tokio::net::TcpListener;
 
-#[derive(Template, Deserialize, Serialize)]
+#[derive(Template)]
 #[template(path = "template.html")]
+pub struct Page {
+    csrf_input: CsrfHiddenInput,
+}
+
+#[derive(Deserialize, Serialize)]
 pub struct Keys {
     authenticity_token: String,
     // Your attributes...
@@ -45,12 +50,12 @@ async fn main() {
 
 // basic handler that responds with a static string
 async fn root(token: CsrfToken) -> impl IntoResponse {
-    let keys = Keys {
-        authenticity_token: token.authenticity_token().unwrap(),
+    let page = Page {
+        csrf_input: token.hidden_input(),
     };
 
     // We must return the token so that into_response will run and add it to our response cookies.
-    (token, keys).into_response()
+    (token, Html(page.render().unwrap())).into_response()
 }
 
 /// Can only be done with the feature layer enabled
@@ -42,15 +42,12 @@ async fn root(token: CsrfToken) -> impl IntoResponse {
 
 <body>
 <form method="post" action="/">
-    <input type="hidden" name="authenticity_token" value="{{ authenticity_token }}"/>
+    {{ csrf_input }}
     <input id="button" type="submit" value="Submit" tabindex="4" />
 </form>
 </body>
 </html>"#
-            .replace(
-                "{{ authenticity_token }}",
-                &token.authenticity_token().unwrap(),
-            ),
+            .replace("{{ csrf_input }}", &token.hidden_input().to_string()),
     );
 
     // We must return the token so that into_response will run and add it to our response cookies.
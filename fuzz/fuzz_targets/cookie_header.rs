@@ -0,0 +1,39 @@
+#![no_main]
+
+use axum_csrf::{test_util::token_from_request, CookieMode, CsrfConfig, Key};
+use http::{header::COOKIE, HeaderValue, Request};
+use libfuzzer_sys::fuzz_target;
+use std::sync::OnceLock;
+
+/// One config per cookie mode, sharing a key so they are only built once.
+fn configs() -> &'static [CsrfConfig; 3] {
+    static CONFIGS: OnceLock<[CsrfConfig; 3]> = OnceLock::new();
+
+    CONFIGS.get_or_init(|| {
+        let key = Key::generate();
+
+        [
+            CookieMode::Plain,
+            CookieMode::Signed(key.clone()),
+            CookieMode::Private(key),
+        ]
+        .map(|mode| CsrfConfig::default().with_cookie_mode(mode))
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte picks the cookie mode, the rest is the Cookie header.
+    let Some((&mode, data)) = data.split_first() else {
+        return;
+    };
+    let Ok(cookie) = HeaderValue::from_bytes(data) else {
+        return;
+    };
+
+    let mut request = Request::new(());
+    request.headers_mut().insert(COOKIE, cookie);
+
+    let config = &configs()[usize::from(mode) % 3];
+    let token = token_from_request(&request, config.clone());
+    token.authenticity_token();
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+use axum_csrf::{test_util::TestToken, CsrfConfig};
+use libfuzzer_sys::fuzz_target;
+use std::sync::OnceLock;
+
+static TOKEN: OnceLock<TestToken> = OnceLock::new();
+
+fuzz_target!(|data: &str| {
+    let token = TOKEN.get_or_init(|| TestToken::new(CsrfConfig::default()));
+    let _ = token.token().verify(data);
+});
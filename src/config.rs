@@ -1,17 +1,89 @@
+use crate::{mac::MAX_MAC_LEN, HmacSha256, TokenEncoding, TokenMac};
 pub use cookie::{Key, SameSite};
-use rand::{distributions::Alphanumeric, thread_rng, Rng};
-use std::borrow::Cow;
+use rand::{rngs::OsRng, CryptoRng, RngCore};
+use std::{
+    borrow::Cow,
+    sync::{Arc, Mutex, PoisonError},
+};
 use time::Duration;
 
+/// How the CSRF cookie is protected before it is sent to the browser.
+#[derive(Clone)]
+pub enum CookieMode {
+    /// The cookie is sent as is. Anyone can read or forge it.
+    Plain,
+    /// The cookie is signed with HMAC so it can be read, but any tampering is detected.
+    /// Useful when javascript needs to read the cookie.
+    Signed(Key),
+    /// The cookie is encrypted with AEAD for confidentiality, integrity, and authenticity.
+    Private(Key),
+}
+
+impl CookieMode {
+    /// Returns the Key used to sign or encrypt the cookie.
+    pub fn key(&self) -> Option<&Key> {
+        match self {
+            CookieMode::Plain => None,
+            CookieMode::Signed(key) | CookieMode::Private(key) => Some(key),
+        }
+    }
+}
+
+impl std::fmt::Debug for CookieMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CookieMode::Plain => f.write_str("Plain"),
+            CookieMode::Signed(_) => f.write_str("Signed(key hidden)"),
+            CookieMode::Private(_) => f.write_str("Private(key hidden)"),
+        }
+    }
+}
+
+/// Fewest random bytes a Token can be made of, 128 bits of entropy.
+const MIN_COOKIE_LEN: usize = 16;
+
+/// What to do when the browser sends more than one CSRF cookie.
+///
+/// A sibling subdomain can plant its own cookie with the same name (cookie tossing), so the
+/// request will carry both and the order they arrive in can not be trusted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateCookiePolicy {
+    /// Issue a new Token and fail verification with [`crate::CsrfError::DuplicateCookie`].
+    ///
+    /// A planted cookie with a more specific Path is sent first, so this is the only policy
+    /// that stops cookie tossing without [`CsrfConfig::with_prefix_with_host`].
+    #[default]
+    Reject,
+    /// Use the first cookie that can be decrypted or verified.
+    PreferValid,
+    /// Use a `__Host-` prefixed cookie if one can be decrypted or verified as other subdomains can not set those,
+    /// otherwise the first unprefixed cookie that can.
+    ///
+    /// Useful while moving to [`CsrfConfig::with_prefix_with_host`], as the unprefixed cookie issued before
+    /// is still read until the prefixed one replaces it.
+    PreferHostPrefix,
+}
+
 ///This is the CSRF Config it is used to manage how we set the Restricted Cookie.
+///
+///The settings are shared behind an [`Arc`] so cloning the Config for each request or Token is cheap.
+///The builder methods copy the settings first if they are already shared.
 #[derive(Clone)]
 pub struct CsrfConfig {
+    pub(crate) inner: Arc<ConfigInner>,
+}
+
+/// Settings shared by every clone of a [`CsrfConfig`].
+#[derive(Clone)]
+pub(crate) struct ConfigInner {
     /// CSRF Cookie lifespan
     pub(crate) lifespan: Duration,
     /// CSRF cookie name
     pub(crate) cookie_name: String,
-    /// CSRF Token character length
+    /// Number of random bytes each CSRF Token is made of
     pub(crate) cookie_len: usize,
+    /// Random number generator Tokens are made from, the OS's when not set.
+    pub(crate) rng: Option<Arc<Mutex<dyn RngCore + Send>>>,
     /// Session cookie domain
     pub(crate) cookie_domain: Option<Cow<'static, str>>,
     /// Session cookie http only flag
@@ -23,34 +95,64 @@ pub struct CsrfConfig {
     pub(crate) cookie_same_site: SameSite,
     /// Session cookie secure flag
     pub(crate) cookie_secure: bool,
-    ///Encyption Key used to encypt cookies for confidentiality, integrity, and authenticity.
-    pub(crate) key: Option<Key>,
-    ///Hashing Salt.
-    pub(crate) salt: Cow<'static, str>,
+    /// How the cookie is protected along with the Key used to sign or encrypt it.
+    pub(crate) cookie_mode: CookieMode,
+    /// Hashing Salt, kept so the MAC can be keyed again when the algorithm changes.
+    pub(crate) salt: Arc<[u8]>,
+    /// Creates the configured MAC from the salt.
+    pub(crate) new_mac: fn(&[u8]) -> Arc<dyn TokenMac>,
+    /// MAC already keyed with the salt, shared by every Token instead of rekeying.
+    pub(crate) mac: Arc<dyn TokenMac>,
+    /// How the MAC within authenticity tokens is encoded.
+    pub(crate) encoding: TokenEncoding,
+    /// Accept authenticity tokens in any encoding instead of only the configured one.
+    pub(crate) tolerant_decoding: bool,
     /// This is used to append __Host- to the front of all Cookie names to prevent sub domain usage.
     /// It is disabled by default.
     pub(crate) prefix_with_host: bool,
+    /// Form field name used when rendering the authenticity token into html.
+    pub(crate) field_name: Cow<'static, str>,
+    /// What to do when more than one CSRF cookie is sent.
+    pub(crate) duplicate_cookies: DuplicateCookiePolicy,
 }
 
 impl std::fmt::Debug for CsrfConfig {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("CsrfConfig")
-            .field("lifespan", &self.lifespan)
-            .field("cookie_name", &self.cookie_name)
-            .field("cookie_len", &self.cookie_len)
-            .field("cookie_domain", &self.cookie_domain)
-            .field("cookie_http_only", &self.cookie_http_only)
-            .field("cookie_path", &self.cookie_path)
-            .field("cookie_same_site", &self.cookie_same_site)
-            .field("cookie_secure", &self.cookie_secure)
-            .field("key", &"key hidden")
+            .field("lifespan", &self.inner.lifespan)
+            .field("cookie_name", &self.inner.cookie_name)
+            .field("cookie_len", &self.inner.cookie_len)
+            .field(
+                "rng",
+                &if self.inner.rng.is_some() {
+                    "custom"
+                } else {
+                    "OsRng"
+                },
+            )
+            .field("cookie_domain", &self.inner.cookie_domain)
+            .field("cookie_http_only", &self.inner.cookie_http_only)
+            .field("cookie_path", &self.inner.cookie_path)
+            .field("cookie_same_site", &self.inner.cookie_same_site)
+            .field("cookie_secure", &self.inner.cookie_secure)
+            .field("cookie_mode", &self.inner.cookie_mode)
             .field("salt", &"salt hidden")
-            .field("prefix_with_host", &self.prefix_with_host)
+            .field("mac", &self.inner.mac.id())
+            .field("encoding", &self.inner.encoding)
+            .field("tolerant_decoding", &self.inner.tolerant_decoding)
+            .field("prefix_with_host", &self.inner.prefix_with_host)
+            .field("field_name", &self.inner.field_name)
+            .field("duplicate_cookies", &self.inner.duplicate_cookies)
             .finish()
     }
 }
 
 impl CsrfConfig {
+    /// Returns the settings to modify, copying them if other clones still share them.
+    fn inner_mut(&mut self) -> &mut ConfigInner {
+        Arc::make_mut(&mut self.inner)
+    }
+
     /// Creates [`Default`] configuration of [`CsrfConfig`].
     /// This is equivalent to the [`CsrfConfig::default()`].
     #[must_use]
@@ -72,7 +174,7 @@ impl CsrfConfig {
     where
         T: Into<Cow<'static, str>>,
     {
-        self.cookie_domain = name.map(|v| v.into());
+        self.inner_mut().cookie_domain = name.map(|v| v.into());
         self
     }
 
@@ -81,14 +183,14 @@ impl CsrfConfig {
     /// # Examples
     /// ```rust
     /// use axum_csrf::CsrfConfig;
-    /// use chrono::Duration;
+    /// use time::Duration;
     ///
     /// let config = CsrfConfig::default().with_lifetime(Duration::days(32));
     /// ```
     ///
     #[must_use]
     pub fn with_lifetime(mut self, time: Duration) -> Self {
-        self.lifespan = time;
+        self.inner_mut().lifespan = time;
         self
     }
 
@@ -103,7 +205,7 @@ impl CsrfConfig {
     ///
     #[must_use]
     pub fn with_cookie_name(mut self, name: &str) -> Self {
-        self.cookie_name = name.into();
+        self.inner_mut().cookie_name = name.into();
         self
     }
 
@@ -121,7 +223,7 @@ impl CsrfConfig {
     ///
     #[must_use]
     pub fn with_cookie_path(mut self, path: impl Into<Cow<'static, str>>) -> Self {
-        self.cookie_path = path.into();
+        self.inner_mut().cookie_path = path.into();
         self
     }
 
@@ -139,7 +241,7 @@ impl CsrfConfig {
     ///
     #[must_use]
     pub fn with_cookie_same_site(mut self, same_site: SameSite) -> Self {
-        self.cookie_same_site = same_site;
+        self.inner_mut().cookie_same_site = same_site;
         self
     }
 
@@ -154,7 +256,7 @@ impl CsrfConfig {
     ///
     #[must_use]
     pub fn with_http_only(mut self, is_set: bool) -> Self {
-        self.cookie_http_only = is_set;
+        self.inner_mut().cookie_http_only = is_set;
         self
     }
 
@@ -169,11 +271,16 @@ impl CsrfConfig {
     ///
     #[must_use]
     pub fn with_secure(mut self, is_set: bool) -> Self {
-        self.cookie_secure = is_set;
+        self.inner_mut().cookie_secure = is_set;
         self
     }
 
-    /// Set's the csrf's token length.
+    /// Set's how many random bytes the csrf's token is made of.
+    ///
+    /// The bytes are base64url encoded within the cookie. Default is 32.
+    ///
+    /// Lengths below 16 bytes (128 bits) are not rejected but clamped, so `with_cookie_len(8)`
+    /// still makes tokens of 16 random bytes.
     ///
     /// # Examples
     /// ```rust
@@ -184,13 +291,38 @@ impl CsrfConfig {
     ///
     #[must_use]
     pub fn with_cookie_len(mut self, length: usize) -> Self {
-        self.cookie_len = length;
+        self.inner_mut().cookie_len = length.max(MIN_COOKIE_LEN);
+        self
+    }
+
+    /// Set's the cryptographically secure random number generator new tokens are made from.
+    ///
+    /// Defaults to the operating system's generator. The generator is shared by every clone of the config
+    /// and locked while a token is made. It must implement the `RngCore` and `CryptoRng` traits of `rand` 0.8,
+    /// generators from another major version of `rand` will not work.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::CsrfConfig;
+    /// use rand::{rngs::StdRng, SeedableRng};
+    ///
+    /// let config = CsrfConfig::default().with_rng(StdRng::from_entropy());
+    /// ```
+    ///
+    #[must_use]
+    pub fn with_rng<R>(mut self, rng: R) -> Self
+    where
+        R: RngCore + CryptoRng + Send + 'static,
+    {
+        self.inner_mut().rng = Some(Arc::new(Mutex::new(rng)));
         self
     }
 
     /// Set's the csrf's cookie encyption key enabling private cookies.
     ///
     /// When Set it will enforce Private cookies across all Sessions.
+    /// If the cookie mode is already [`CookieMode::Signed`] the key is used for signing instead.
+    /// Setting `None` switches to [`CookieMode::Plain`].
     /// If you use Key::generate() it will make a new key each server reboot.
     /// To prevent this make and save a key to a config file for long term usage.
     /// For Extra Security Regenerate the key every so many months to a year.
@@ -199,12 +331,35 @@ impl CsrfConfig {
     /// ```rust
     /// use axum_csrf::{Key, CsrfConfig};
     ///
-    /// let config = CsrfConfig::default().with_key(Key::generate());
+    /// let config = CsrfConfig::default().with_key(Some(Key::generate()));
     /// ```
     ///
     #[must_use]
     pub fn with_key(mut self, key: Option<Key>) -> Self {
-        self.key = key;
+        self.inner_mut().cookie_mode = match (key, &self.inner.cookie_mode) {
+            (Some(key), CookieMode::Signed(_)) => CookieMode::Signed(key),
+            (Some(key), _) => CookieMode::Private(key),
+            (None, _) => CookieMode::Plain,
+        };
+        self
+    }
+
+    /// Set's how the csrf's cookie is protected.
+    ///
+    /// [`CookieMode::Private`] is the default and encrypts the cookie.
+    /// [`CookieMode::Signed`] leaves the cookie readable, for example by javascript, while still detecting tampering.
+    /// [`CookieMode::Plain`] does neither and lets the cookie be forged, so only use it if something else protects it.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::{CookieMode, CsrfConfig, Key};
+    ///
+    /// let config = CsrfConfig::default().with_cookie_mode(CookieMode::Signed(Key::generate()));
+    /// ```
+    ///
+    #[must_use]
+    pub fn with_cookie_mode(mut self, mode: CookieMode) -> Self {
+        self.inner_mut().cookie_mode = mode;
         self
     }
 
@@ -221,7 +376,75 @@ impl CsrfConfig {
     ///
     #[must_use]
     pub fn with_salt(mut self, salt: impl Into<Cow<'static, str>>) -> Self {
-        self.salt = salt.into();
+        let inner = self.inner_mut();
+        inner.salt = salt.into().as_bytes().into();
+        inner.mac = (inner.new_mac)(&inner.salt);
+        self
+    }
+
+    /// Set's the keyed hash used to create authenticity tokens from the csrf's token.
+    ///
+    /// Default is [`HmacSha256`]. The MAC is keyed with the configured salt.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the MAC is longer than 64 bytes.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::{CsrfConfig, HmacSha512};
+    ///
+    /// let config = CsrfConfig::default().with_mac::<HmacSha512>();
+    /// ```
+    ///
+    #[must_use]
+    pub fn with_mac<M: TokenMac>(mut self) -> Self {
+        let inner = self.inner_mut();
+        inner.new_mac = |salt| Arc::new(M::new_keyed(salt));
+        inner.mac = (inner.new_mac)(&inner.salt);
+
+        assert!(
+            inner.mac.output_len() <= MAX_MAC_LEN,
+            "TokenMac output is longer than {MAX_MAC_LEN} bytes"
+        );
+        self
+    }
+
+    /// Set's how the MAC within authenticity tokens is encoded.
+    ///
+    /// Default is [`TokenEncoding::Base64`]. Use [`TokenEncoding::Base64Url`] or [`TokenEncoding::Hex`]
+    /// when tokens are placed in query strings or headers without percent encoding them.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::{CsrfConfig, TokenEncoding};
+    ///
+    /// let config = CsrfConfig::default().with_encoding(TokenEncoding::Base64Url);
+    /// ```
+    ///
+    #[must_use]
+    pub fn with_encoding(mut self, encoding: TokenEncoding) -> Self {
+        self.inner_mut().encoding = encoding;
+        self
+    }
+
+    /// Set's if authenticity tokens in any [`TokenEncoding`] are accepted, not only the configured one.
+    ///
+    /// Useful while changing the encoding as pages rendered before the change still hold the previous one.
+    /// Disabled by default.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::{CsrfConfig, TokenEncoding};
+    ///
+    /// let config = CsrfConfig::default()
+    ///     .with_encoding(TokenEncoding::Base64Url)
+    ///     .with_tolerant_decoding(true);
+    /// ```
+    ///
+    #[must_use]
+    pub fn with_tolerant_decoding(mut self, enable: bool) -> Self {
+        self.inner_mut().tolerant_decoding = enable;
         self
     }
 
@@ -239,14 +462,109 @@ impl CsrfConfig {
     ///
     #[must_use]
     pub fn with_prefix_with_host(mut self, enable: bool) -> Self {
-        self.prefix_with_host = enable;
+        self.inner_mut().prefix_with_host = enable;
         self
     }
+
+    /// Set's what to do when the request has more than one CSRF cookie.
+    ///
+    /// Defaults to [`DuplicateCookiePolicy::Reject`].
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::{CsrfConfig, DuplicateCookiePolicy};
+    ///
+    /// let config = CsrfConfig::default().with_duplicate_cookies(DuplicateCookiePolicy::PreferHostPrefix);
+    /// ```
+    ///
+    #[must_use]
+    pub fn with_duplicate_cookies(mut self, policy: DuplicateCookiePolicy) -> Self {
+        self.inner_mut().duplicate_cookies = policy;
+        self
+    }
+
+    /// Set's the form field name used when rendering the authenticity token into html.
+    ///
+    /// This is the name given to the hidden input and the `csrf-param` meta tag.
+    /// Default is "authenticity_token".
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::CsrfConfig;
+    ///
+    /// let config = CsrfConfig::default().with_field_name("csrf_token");
+    /// ```
+    ///
+    #[must_use]
+    pub fn with_field_name(mut self, name: impl Into<Cow<'static, str>>) -> Self {
+        self.inner_mut().field_name = name.into();
+        self
+    }
+
+    /// Returns how the csrf's cookie is protected.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::{CookieMode, CsrfConfig};
+    ///
+    /// let config = CsrfConfig::default().with_cookie_mode(CookieMode::Plain);
+    /// assert!(matches!(config.cookie_mode(), CookieMode::Plain));
+    /// ```
+    ///
+    #[must_use]
+    pub fn cookie_mode(&self) -> &CookieMode {
+        &self.inner.cookie_mode
+    }
+
+    /// Returns the Key the csrf's cookie is signed or encrypted with, `None` for [`CookieMode::Plain`].
+    ///
+    /// Use it to build the `axum_extra` jar that the Token is added to, so both share the same Key.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::{CsrfConfig, Key};
+    ///
+    /// let key = Key::generate();
+    /// let config = CsrfConfig::default().with_key(Some(key.clone()));
+    /// assert!(config.key() == Some(&key));
+    /// ```
+    ///
+    #[must_use]
+    pub fn key(&self) -> Option<&Key> {
+        self.inner.cookie_mode.key()
+    }
+}
+
+impl CsrfConfig {
+    /// Fills the buffer with random bytes from the configured generator.
+    pub(crate) fn fill_random(&self, dest: &mut [u8]) {
+        match &self.inner.rng {
+            Some(rng) => rng
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .fill_bytes(dest),
+            None => OsRng.fill_bytes(dest),
+        }
+    }
+
+    /// Returns the cookie name with __Host- prepended when `prefix_with_host` is enabled.
+    pub(crate) fn prefixed_cookie_name(&self) -> String {
+        if self.inner.prefix_with_host {
+            let mut prefixed =
+                String::with_capacity(self.inner.cookie_name.len() + "__Host-".len());
+            prefixed.push_str("__Host-");
+            prefixed.push_str(&self.inner.cookie_name);
+            prefixed
+        } else {
+            self.inner.cookie_name.clone()
+        }
+    }
 }
 
 impl Default for CsrfConfig {
     fn default() -> Self {
-        Self {
+        let salt: Arc<[u8]> = random_salt().into();
+        let inner = ConfigInner {
             // Set to 6hour for default in Database Session stores.
             lifespan: Duration::hours(6),
             cookie_name: "Csrf_Token".into(),
@@ -257,14 +575,27 @@ impl Default for CsrfConfig {
             cookie_same_site: SameSite::Lax,
             cookie_len: 32,
             //We do this by default since we always want this to be secure.
-            key: Some(Key::generate()),
-            salt: thread_rng()
-                .sample_iter(&Alphanumeric)
-                .take(32)
-                .map(char::from)
-                .collect::<String>()
-                .into(),
+            cookie_mode: CookieMode::Private(Key::generate()),
+            rng: None,
+            mac: Arc::new(HmacSha256::new_keyed(&salt)),
+            new_mac: |salt| Arc::new(HmacSha256::new_keyed(salt)),
+            salt,
+            encoding: TokenEncoding::default(),
+            tolerant_decoding: false,
             prefix_with_host: false,
+            field_name: "authenticity_token".into(),
+            duplicate_cookies: DuplicateCookiePolicy::default(),
+        };
+
+        Self {
+            inner: Arc::new(inner),
         }
     }
 }
+
+/// Creates the random salt used when none is configured.
+fn random_salt() -> [u8; 32] {
+    let mut salt = [0; 32];
+    OsRng.fill_bytes(&mut salt);
+    salt
+}
@@ -0,0 +1,114 @@
+use crate::{CsrfError, RequiredHeader};
+use http::{header::CONTENT_TYPE, request::Parts};
+use std::sync::Arc;
+
+/// Token-less CSRF protection for JSON APIs, set with [`crate::CsrfLayer::with_content_type_policy`].
+///
+/// Cross-site html forms can only send `application/x-www-form-urlencoded`, `multipart/form-data` or
+/// `text/plain` bodies, and any other Content-Type or a custom header makes the browser send a CORS
+/// preflight first. Unsafe requests to the policy's routes must use one of the allowed Content-Types,
+/// and carry the required header when one is set, instead of an authenticity token.
+///
+/// Only safe as long as your CORS configuration does not allow untrusted origins.
+///
+/// # Examples
+/// ```rust
+/// use axum_csrf::{ContentTypePolicy, RequiredHeader};
+///
+/// let policy = ContentTypePolicy::new(["application/json"])
+///     .with_routes(["/api/"])
+///     .with_required_header(RequiredHeader::new("x-requested-with"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct ContentTypePolicy {
+    pub(crate) allowed: Arc<[String]>,
+    pub(crate) routes: Arc<[String]>,
+    pub(crate) required_header: Option<RequiredHeader>,
+}
+
+impl ContentTypePolicy {
+    /// Creates the policy allowing these Content-Types, compared without their parameters and ignoring case.
+    ///
+    /// It applies to every route until [`ContentTypePolicy::with_routes`] is set.
+    pub fn new<I>(allowed: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        Self {
+            allowed: allowed
+                .into_iter()
+                .map(|mime| mime.into().to_ascii_lowercase())
+                .collect(),
+            routes: Arc::new([]),
+            required_header: None,
+        }
+    }
+
+    /// Set's the path prefixes the policy applies to, such as `/api/`. Other paths still need an authenticity token.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::ContentTypePolicy;
+    ///
+    /// let policy = ContentTypePolicy::new(["application/json"]).with_routes(["/api/", "/graphql"]);
+    /// ```
+    ///
+    #[must_use]
+    pub fn with_routes<I>(mut self, prefixes: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.routes = prefixes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set's a header which must also be sent, such as `X-Requested-With`.
+    ///
+    /// Requests without it fail with [`CsrfError::MissingHeader`], the same as the layer's own
+    /// [`crate::CsrfLayer::with_required_header`] which is not checked on the policy's routes.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::{ContentTypePolicy, RequiredHeader};
+    ///
+    /// let policy = ContentTypePolicy::new(["application/json"])
+    ///     .with_required_header(RequiredHeader::new("x-requested-with"));
+    /// ```
+    ///
+    #[must_use]
+    pub fn with_required_header(mut self, header: RequiredHeader) -> Self {
+        self.required_header = Some(header);
+        self
+    }
+
+    /// Returns true if the policy applies to the path.
+    pub(crate) fn applies_to(&self, path: &str) -> bool {
+        self.routes.is_empty()
+            || self
+                .routes
+                .iter()
+                .any(|prefix| path.starts_with(prefix.as_str()))
+    }
+
+    /// Checks the request's Content-Type and required header.
+    pub(crate) fn check(&self, parts: &Parts) -> Result<(), CsrfError> {
+        let allowed = parts
+            .headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map(|mime| mime.trim().to_ascii_lowercase())
+            .is_some_and(|mime| self.allowed.contains(&mime));
+
+        if !allowed {
+            return Err(CsrfError::ContentType);
+        }
+
+        match &self.required_header {
+            Some(header) => header.check(parts),
+            None => Ok(()),
+        }
+    }
+}
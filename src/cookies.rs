@@ -1,50 +1,71 @@
-use crate::CsrfConfig;
-use cookie::{Cookie, CookieJar, Key};
+use crate::{token::TokenSource, CookieMode, CsrfConfig, DuplicateCookiePolicy};
+use base64ct::{Base64UrlUnpadded, Encoding};
+use cookie::{Cookie, CookieJar};
 use http::{
     self,
     header::{COOKIE, SET_COOKIE},
     HeaderMap,
 };
-use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use std::borrow::Cow;
+use time::OffsetDateTime;
 
 pub(crate) trait CookiesExt {
-    fn get_cookie(&self, name: &str, key: &Option<Key>) -> Option<Cookie<'static>>;
-    fn add_cookie(&mut self, cookie: Cookie<'static>, key: &Option<Key>);
+    fn add_cookie(&mut self, cookie: Cookie<'static>, mode: &CookieMode);
 }
 
 impl CookiesExt for CookieJar {
-    fn get_cookie(&self, name: &str, key: &Option<Key>) -> Option<Cookie<'static>> {
-        if let Some(key) = key {
-            self.private(key).get(name)
-        } else {
-            self.get(name).cloned()
-        }
-    }
-
-    fn add_cookie(&mut self, cookie: Cookie<'static>, key: &Option<Key>) {
-        if let Some(key) = key {
-            self.private_mut(key).add(cookie)
-        } else {
-            self.add(cookie)
+    fn add_cookie(&mut self, cookie: Cookie<'static>, mode: &CookieMode) {
+        match mode {
+            CookieMode::Plain => self.add(cookie),
+            CookieMode::Signed(key) => self.signed_mut(key).add(cookie),
+            CookieMode::Private(key) => self.private_mut(key).add(cookie),
         }
     }
 }
 
-pub(crate) fn get_cookies(headers: &mut HeaderMap) -> CookieJar {
-    let mut jar = CookieJar::new();
-
-    let cookie_iter = headers
+/// Iterates over the values of every cookie named `name` across all the Cookie headers, in the order sent.
+///
+/// Other cookies are skipped without being parsed and values are only copied when they need percent decoding.
+pub(crate) fn find_cookies<'a>(
+    headers: &'a HeaderMap,
+    name: &'a str,
+) -> impl Iterator<Item = Cow<'a, str>> + 'a {
+    headers
         .get_all(COOKIE)
         .into_iter()
         .filter_map(|value| value.to_str().ok())
         .flat_map(|value| value.split(';'))
-        .filter_map(|cookie| Cookie::parse_encoded(cookie.to_owned()).ok());
+        .filter_map(move |pair| {
+            let (cookie_name, value) = pair.split_once('=')?;
 
-    for cookie in cookie_iter {
-        jar.add_original(cookie);
-    }
+            if cookie_name.trim() != name {
+                return None;
+            }
 
-    jar
+            let value = value.trim();
+
+            if !value.contains('%') {
+                return Some(Cow::Borrowed(value));
+            }
+
+            Cookie::parse_encoded(pair.trim())
+                .ok()
+                .map(|cookie| Cow::Owned(cookie.value().to_owned()))
+        })
+}
+
+/// Verifies or decrypts a single cookie value, returning the plain value if it was not tampered with.
+pub(crate) fn decode_cookie(name: &str, value: &str, mode: &CookieMode) -> Option<String> {
+    let cookie = Cookie::new(name.to_owned(), value.to_owned());
+    let jar = CookieJar::new();
+
+    let cookie = match mode {
+        CookieMode::Plain => Some(cookie),
+        CookieMode::Signed(key) => jar.signed(key).verify(cookie),
+        CookieMode::Private(key) => jar.private(key).decrypt(cookie),
+    };
+
+    cookie.map(|cookie| cookie.value().to_owned())
 }
 
 pub(crate) fn set_cookies(jar: CookieJar, headers: &mut HeaderMap) {
@@ -55,26 +76,395 @@ pub(crate) fn set_cookies(jar: CookieJar, headers: &mut HeaderMap) {
     }
 }
 
-pub(crate) fn get_token(config: &CsrfConfig, headers: &mut HeaderMap) -> String {
-    let cookie_jar = get_cookies(headers);
-    let mut prefixed = String::with_capacity(config.cookie_name.len() + "__Host-".len());
-
-    if config.prefix_with_host {
-        prefixed.push_str("__Host-");
-        prefixed.push_str(&config.cookie_name);
-    } else {
-        prefixed.push_str(&config.cookie_name);
-    }
-
-    //We check if the Cookie Exists as a signed Cookie or not. If so we use the value of the cookie.
-    //If not we create a new one.
-    if let Some(cookie) = cookie_jar.get_cookie(&prefixed, &config.key) {
-        cookie.value().to_owned()
-    } else {
-        thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(config.cookie_len)
-            .map(char::from)
+/// Somewhere the CSRF cookie's raw values can be looked up by name.
+pub(crate) trait CookieSource {
+    /// Iterates over the values of every cookie named `name`, in the order sent.
+    fn cookie_values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = Cow<'a, str>> + 'a;
+}
+
+impl CookieSource for HeaderMap {
+    fn cookie_values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = Cow<'a, str>> + 'a {
+        find_cookies(self, name)
+    }
+}
+
+/// Reads the Token from the request's CSRF cookie, returning why there is none when it is missing or invalid.
+pub(crate) fn get_token(config: &CsrfConfig, headers: &HeaderMap) -> (Option<String>, TokenSource) {
+    read_token(config, headers, &config.inner.cookie_mode)
+}
+
+/// Reads the Token from the cookies, decoding them with `mode` and applying the duplicate cookie policy.
+pub(crate) fn read_token(
+    config: &CsrfConfig,
+    cookies: &impl CookieSource,
+    mode: &CookieMode,
+) -> (Option<String>, TokenSource) {
+    let prefixed = config.prefixed_cookie_name();
+
+    // With prefix_with_host set only the `__Host-` cookie is read so a sibling subdomain can not plant one,
+    // unless the unprefixed cookie is still accepted while migrating.
+    let (cookie, sent) = match config.inner.duplicate_cookies {
+        DuplicateCookiePolicy::Reject => {
+            let mut values = cookies.cookie_values(&prefixed);
+
+            match (values.next(), values.next()) {
+                (Some(_), Some(_)) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(
+                        "more than one CSRF cookie was sent, a new token will be issued"
+                    );
+
+                    return (None, TokenSource::CookieDuplicate);
+                }
+                (value, _) => {
+                    let sent = usize::from(value.is_some());
+                    (
+                        value.and_then(|value| decode_cookie(&prefixed, &value, mode)),
+                        sent,
+                    )
+                }
+            }
+        }
+        DuplicateCookiePolicy::PreferHostPrefix => {
+            let name = &config.inner.cookie_name;
+            let host = format!("__Host-{name}");
+
+            match first_valid(cookies, &host, mode) {
+                (Some(cookie), sent) => (Some(cookie), sent),
+                (None, host_sent) => {
+                    let (cookie, sent) = first_valid(cookies, name, mode);
+                    (cookie, host_sent + sent)
+                }
+            }
+        }
+        DuplicateCookiePolicy::PreferValid => first_valid(cookies, &prefixed, mode),
+    };
+
+    #[cfg(feature = "tracing")]
+    {
+        if sent > 0 && cookie.is_none() {
+            tracing::warn!(
+                cookies_sent = sent,
+                cookie_decodable = false,
+                "CSRF cookie could not be decoded, a new token will be issued"
+            );
+        } else if sent > 1 {
+            tracing::warn!(
+                cookies_sent = sent,
+                "more than one CSRF cookie was sent, using the first that could be decoded"
+            );
+        } else {
+            tracing::trace!(
+                cookie_present = sent > 0,
+                cookie_decodable = cookie.is_some(),
+                "CSRF cookie read"
+            );
+        }
+    }
+
+    match cookie {
+        Some(cookie) => (Some(cookie), TokenSource::Cookie),
+        None if sent > 0 => (None, TokenSource::CookieInvalid),
+        None => (None, TokenSource::Fresh),
+    }
+}
+
+/// Decodes the first cookie named `name` that is not tampered with, along with how many were tried.
+fn first_valid(
+    cookies: &impl CookieSource,
+    name: &str,
+    mode: &CookieMode,
+) -> (Option<String>, usize) {
+    let mut tried = 0;
+
+    for value in cookies.cookie_values(name) {
+        tried += 1;
+
+        if let Some(cookie) = decode_cookie(name, &value, mode) {
+            return (Some(cookie), tried);
+        }
+    }
+
+    (None, tried)
+}
+
+/// Creates a new Token from `cookie_len` random bytes, base64url encoded and ending with the unix time it was issued at.
+pub(crate) fn generate_token(config: &CsrfConfig) -> String {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(len = config.inner.cookie_len, "issued new CSRF token");
+
+    let mut bytes = vec![0; config.inner.cookie_len];
+    config.fill_random(&mut bytes);
+
+    let mut token = Base64UrlUnpadded::encode_string(&bytes);
+    token.push('.');
+    token.push_str(&OffsetDateTime::now_utc().unix_timestamp().to_string());
+    token
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CsrfConfig, DuplicateCookiePolicy, Key};
+    use http::HeaderValue;
+
+    fn headers(cookies: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+
+        for cookie in cookies {
+            headers.append(COOKIE, HeaderValue::from_str(cookie).unwrap());
+        }
+
+        headers
+    }
+
+    fn encode(config: &CsrfConfig, name: &str, value: &str) -> String {
+        let mut jar = CookieJar::new();
+        jar.add_cookie(
+            Cookie::new(name.to_owned(), value.to_owned()),
+            &config.inner.cookie_mode,
+        );
+        jar.get(name).unwrap().stripped().encoded().to_string()
+    }
+
+    fn find(cookies: &[&str], name: &str) -> Vec<String> {
+        find_cookies(&headers(cookies), name)
+            .map(Cow::into_owned)
             .collect()
     }
+
+    #[test]
+    fn find_cookies_scans_all_headers() {
+        let cookies = ["a=1; b=2", "c=3;b=4", "invalid; b = 5 "];
+
+        assert_eq!(find(&cookies, "a"), ["1"]);
+        assert_eq!(find(&cookies, "b"), ["2", "4", "5"]);
+        assert!(find(&cookies, "invalid").is_empty());
+        assert!(find(&cookies, "d").is_empty());
+    }
+
+    #[test]
+    fn find_cookies_borrows_unless_decoding() {
+        let headers = headers(&["a=plain; b=hello%20world"]);
+
+        assert!(matches!(
+            find_cookies(&headers, "a").next(),
+            Some(Cow::Borrowed("plain"))
+        ));
+        assert_eq!(
+            find_cookies(&headers, "b").next().as_deref(),
+            Some("hello world")
+        );
+    }
+
+    #[test]
+    fn get_token_uses_first_cookie() {
+        let config = CsrfConfig::default()
+            .with_key(None)
+            .with_duplicate_cookies(DuplicateCookiePolicy::PreferValid);
+
+        assert_eq!(
+            get_token(
+                &config,
+                &headers(&["Csrf_Token=first", "Csrf_Token=second"])
+            ),
+            (Some("first".to_owned()), TokenSource::Cookie)
+        );
+    }
+
+    #[test]
+    fn get_token_without_cookie_is_fresh() {
+        let config = CsrfConfig::default();
+
+        assert_eq!(
+            get_token(&config, &HeaderMap::new()),
+            (None, TokenSource::Fresh)
+        );
+    }
+
+    #[test]
+    fn get_token_reads_private_cookie() {
+        let config = CsrfConfig::default();
+        let cookie = encode(&config, "Csrf_Token", "secret");
+
+        assert_eq!(
+            get_token(&config, &headers(&[&cookie])),
+            (Some("secret".to_owned()), TokenSource::Cookie)
+        );
+    }
+
+    #[test]
+    fn get_token_reads_signed_cookie() {
+        let config = CsrfConfig::default().with_cookie_mode(CookieMode::Signed(Key::generate()));
+        let cookie = encode(&config, "Csrf_Token", "secret");
+
+        assert!(cookie.contains("secret"));
+        assert_eq!(
+            get_token(&config, &headers(&[&cookie])),
+            (Some("secret".to_owned()), TokenSource::Cookie)
+        );
+    }
+
+    #[test]
+    fn get_token_reads_plain_cookie() {
+        let config = CsrfConfig::default().with_key(None);
+
+        assert_eq!(
+            get_token(&config, &headers(&["Csrf_Token=secret"])),
+            (Some("secret".to_owned()), TokenSource::Cookie)
+        );
+    }
+
+    #[test]
+    fn get_token_rejects_cookie_from_other_key() {
+        let config = CsrfConfig::default();
+        let other = CsrfConfig::default();
+        let cookie = encode(&other, "Csrf_Token", "secret");
+
+        assert_eq!(
+            get_token(&config, &headers(&[&cookie])),
+            (None, TokenSource::CookieInvalid)
+        );
+    }
+
+    #[test]
+    fn get_token_rejects_unsigned_cookie_in_signed_mode() {
+        let config = CsrfConfig::default().with_cookie_mode(CookieMode::Signed(Key::generate()));
+
+        assert_eq!(
+            get_token(&config, &headers(&["Csrf_Token=secret"])),
+            (None, TokenSource::CookieInvalid)
+        );
+    }
+
+    #[test]
+    fn get_token_uses_host_prefix() {
+        let config = CsrfConfig::default().with_prefix_with_host(true);
+        let unprefixed = encode(&config, "Csrf_Token", "unprefixed");
+        let prefixed = encode(&config, "__Host-Csrf_Token", "prefixed");
+
+        assert_eq!(
+            get_token(&config, &headers(&[&unprefixed])),
+            (None, TokenSource::Fresh)
+        );
+        assert_eq!(
+            get_token(&config, &headers(&[&unprefixed, &prefixed])),
+            (Some("prefixed".to_owned()), TokenSource::Cookie)
+        );
+    }
+
+    #[test]
+    fn duplicate_cookies_prefer_valid() {
+        let config =
+            CsrfConfig::default().with_duplicate_cookies(DuplicateCookiePolicy::PreferValid);
+        let cookie = encode(&config, "Csrf_Token", "secret");
+
+        assert_eq!(
+            get_token(&config, &headers(&["Csrf_Token=planted", &cookie])),
+            (Some("secret".to_owned()), TokenSource::Cookie)
+        );
+        assert_eq!(
+            get_token(&config, &headers(&["Csrf_Token=planted; Csrf_Token=other"])),
+            (None, TokenSource::CookieInvalid)
+        );
+    }
+
+    #[test]
+    fn duplicate_cookies_reject() {
+        let config = CsrfConfig::default();
+        let cookie = encode(&config, "Csrf_Token", "secret");
+
+        assert_eq!(
+            config.inner.duplicate_cookies,
+            DuplicateCookiePolicy::Reject
+        );
+
+        assert_eq!(
+            get_token(&config, &headers(&[&cookie])),
+            (Some("secret".to_owned()), TokenSource::Cookie)
+        );
+        assert_eq!(
+            get_token(&config, &headers(&[&cookie, &cookie])),
+            (None, TokenSource::CookieDuplicate)
+        );
+    }
+
+    #[test]
+    fn duplicate_cookies_prefer_host_prefix() {
+        let config =
+            CsrfConfig::default().with_duplicate_cookies(DuplicateCookiePolicy::PreferHostPrefix);
+        let unprefixed = encode(&config, "Csrf_Token", "unprefixed");
+        let prefixed = encode(&config, "__Host-Csrf_Token", "prefixed");
+
+        assert_eq!(
+            get_token(&config, &headers(&[&unprefixed, &prefixed])),
+            (Some("prefixed".to_owned()), TokenSource::Cookie)
+        );
+        assert_eq!(
+            get_token(&config, &headers(&[&unprefixed])),
+            (Some("unprefixed".to_owned()), TokenSource::Cookie)
+        );
+        assert_eq!(
+            get_token(
+                &config,
+                &headers(&["__Host-Csrf_Token=planted", &unprefixed])
+            ),
+            (Some("unprefixed".to_owned()), TokenSource::Cookie)
+        );
+    }
+
+    #[test]
+    fn duplicate_cookies_prefer_host_prefix_while_migrating() {
+        let config = CsrfConfig::default()
+            .with_prefix_with_host(true)
+            .with_duplicate_cookies(DuplicateCookiePolicy::PreferHostPrefix);
+        let unprefixed = encode(&config, "Csrf_Token", "unprefixed");
+        let prefixed = encode(&config, "__Host-Csrf_Token", "prefixed");
+
+        assert_eq!(
+            get_token(&config, &headers(&[&unprefixed])),
+            (Some("unprefixed".to_owned()), TokenSource::Cookie)
+        );
+        assert_eq!(
+            get_token(&config, &headers(&[&unprefixed, &prefixed])),
+            (Some("prefixed".to_owned()), TokenSource::Cookie)
+        );
+    }
+
+    #[test]
+    fn generate_token_enforces_minimum_length() {
+        let token = generate_token(&CsrfConfig::default().with_cookie_len(4));
+        let (random, _) = token.rsplit_once('.').unwrap();
+
+        assert_eq!(Base64UrlUnpadded::decode_vec(random).unwrap().len(), 16);
+    }
+
+    #[test]
+    fn generate_token_uses_configured_rng() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let first = CsrfConfig::default().with_rng(StdRng::seed_from_u64(7));
+        let second = CsrfConfig::default().with_rng(StdRng::seed_from_u64(7));
+        let random = |token: String| token.rsplit_once('.').unwrap().0.to_owned();
+
+        assert_eq!(
+            random(generate_token(&first)),
+            random(generate_token(&second))
+        );
+        assert_ne!(
+            random(generate_token(&first)),
+            random(generate_token(&first))
+        );
+    }
+
+    #[test]
+    fn generate_token_has_length_and_issue_time() {
+        let config = CsrfConfig::default().with_cookie_len(16);
+        let token = generate_token(&config);
+        let (random, issued_at) = token.rsplit_once('.').unwrap();
+
+        assert_eq!(Base64UrlUnpadded::decode_vec(random).unwrap().len(), 16);
+        assert!(issued_at.parse::<i64>().is_ok());
+        assert_ne!(token, generate_token(&config));
+    }
 }
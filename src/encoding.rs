@@ -0,0 +1,202 @@
+use base64ct::{Base64, Base64UrlUnpadded, Encoding};
+
+/// How the MAC within authenticity tokens is encoded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TokenEncoding {
+    /// Standard base64 with padding. Needs percent encoding within urls.
+    #[default]
+    Base64,
+    /// URL safe base64 without padding, safe to place in query strings and headers as is.
+    Base64Url,
+    /// Lowercase hex, uppercase is also accepted when decoding.
+    Hex,
+}
+
+/// Why an encoded MAC could not be decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum DecodeError {
+    /// The value is not valid for the encoding.
+    Invalid,
+    /// The value decodes to this many bytes, more than fit within the buffer.
+    TooLong(usize),
+}
+
+impl TokenEncoding {
+    const ALL: [TokenEncoding; 3] = [
+        TokenEncoding::Base64,
+        TokenEncoding::Base64Url,
+        TokenEncoding::Hex,
+    ];
+
+    /// Appends the encoded bytes to the string.
+    pub(crate) fn encode(self, bytes: &[u8], out: &mut String) {
+        match self {
+            TokenEncoding::Base64 => out.push_str(&Base64::encode_string(bytes)),
+            TokenEncoding::Base64Url => out.push_str(&Base64UrlUnpadded::encode_string(bytes)),
+            TokenEncoding::Hex => {
+                const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+                for byte in bytes {
+                    out.push(char::from(DIGITS[usize::from(byte >> 4)]));
+                    out.push(char::from(DIGITS[usize::from(byte & 0xf)]));
+                }
+            }
+        }
+    }
+
+    /// Decodes into the buffer returning the number of bytes written.
+    pub(crate) fn decode(self, encoded: &str, buf: &mut [u8]) -> Result<usize, DecodeError> {
+        let decoded = match self {
+            TokenEncoding::Base64 => Base64::decode(encoded, buf).map(|decoded| decoded.len()),
+            TokenEncoding::Base64Url => {
+                Base64UrlUnpadded::decode(encoded, buf).map(|decoded| decoded.len())
+            }
+            TokenEncoding::Hex => return decode_hex(encoded, buf),
+        };
+
+        match decoded {
+            Ok(len) => Ok(len),
+            // Anything decoding to more than the buffer holds does not fit within it.
+            Err(base64ct::Error::InvalidLength) => Err(DecodeError::TooLong(base64_len(encoded))),
+            Err(_) => Err(DecodeError::Invalid),
+        }
+    }
+
+    /// Decodes with this encoding, falling back to the others when it does not give `expected` bytes.
+    pub(crate) fn decode_tolerant(
+        self,
+        encoded: &str,
+        buf: &mut [u8],
+        expected: usize,
+    ) -> Result<usize, DecodeError> {
+        let decoded = self.decode(encoded, buf);
+
+        if decoded == Ok(expected) {
+            return decoded;
+        }
+
+        Self::ALL
+            .into_iter()
+            .filter(|encoding| *encoding != self)
+            .find(|encoding| encoding.decode(encoded, buf) == Ok(expected))
+            .map_or(decoded, |_| Ok(expected))
+    }
+}
+
+/// Number of bytes the base64, padded or not, decodes to.
+fn base64_len(encoded: &str) -> usize {
+    let encoded = encoded.trim_end_matches('=');
+    encoded.len() * 3 / 4
+}
+
+fn decode_hex(encoded: &str, buf: &mut [u8]) -> Result<usize, DecodeError> {
+    let encoded = encoded.as_bytes();
+
+    if !encoded.len().is_multiple_of(2) {
+        return Err(DecodeError::Invalid);
+    }
+
+    let len = encoded.len() / 2;
+
+    if len > buf.len() {
+        return Err(DecodeError::TooLong(len));
+    }
+
+    for (byte, pair) in buf.iter_mut().zip(encoded.chunks_exact(2)) {
+        *byte = (nibble(pair[0])? << 4) | nibble(pair[1])?;
+    }
+
+    Ok(len)
+}
+
+fn nibble(digit: u8) -> Result<u8, DecodeError> {
+    match digit {
+        b'0'..=b'9' => Ok(digit - b'0'),
+        b'a'..=b'f' => Ok(digit - b'a' + 10),
+        b'A'..=b'F' => Ok(digit - b'A' + 10),
+        _ => Err(DecodeError::Invalid),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(encoding: TokenEncoding, bytes: &[u8]) -> String {
+        let mut out = String::new();
+        encoding.encode(bytes, &mut out);
+        out
+    }
+
+    #[test]
+    fn encodings_round_trip() {
+        let bytes = [0xfb, 0xff, 0x00, 0x7e, 0x3f];
+
+        assert_eq!(encode(TokenEncoding::Base64, &bytes), "+/8Afj8=");
+        assert_eq!(encode(TokenEncoding::Base64Url, &bytes), "-_8Afj8");
+        assert_eq!(encode(TokenEncoding::Hex, &bytes), "fbff007e3f");
+
+        for encoding in TokenEncoding::ALL {
+            let mut buf = [0; 8];
+            let len = encoding
+                .decode(&encode(encoding, &bytes), &mut buf)
+                .unwrap();
+
+            assert_eq!(&buf[..len], bytes);
+        }
+    }
+
+    #[test]
+    fn hex_accepts_uppercase_and_rejects_invalid() {
+        let mut buf = [0; 4];
+
+        assert_eq!(TokenEncoding::Hex.decode("FBff", &mut buf), Ok(2));
+        assert_eq!(&buf[..2], [0xfb, 0xff]);
+        assert_eq!(
+            TokenEncoding::Hex.decode("fbf", &mut buf),
+            Err(DecodeError::Invalid)
+        );
+        assert_eq!(
+            TokenEncoding::Hex.decode("zz", &mut buf),
+            Err(DecodeError::Invalid)
+        );
+        assert_eq!(
+            TokenEncoding::Hex.decode("0011223344", &mut buf),
+            Err(DecodeError::TooLong(5))
+        );
+    }
+
+    #[test]
+    fn too_long_reports_decoded_length() {
+        let mut buf = [0; 4];
+
+        assert_eq!(
+            TokenEncoding::Base64.decode(&encode(TokenEncoding::Base64, &[0; 34]), &mut buf),
+            Err(DecodeError::TooLong(34))
+        );
+        assert_eq!(
+            TokenEncoding::Base64Url.decode(&encode(TokenEncoding::Base64Url, &[0; 34]), &mut buf),
+            Err(DecodeError::TooLong(34))
+        );
+    }
+
+    #[test]
+    fn tolerant_decoding_tries_other_encodings() {
+        let bytes = [0xfb; 32];
+        let mut buf = [0; 64];
+
+        for encoding in TokenEncoding::ALL {
+            let encoded = encode(encoding, &bytes);
+
+            for configured in TokenEncoding::ALL {
+                assert_eq!(configured.decode_tolerant(&encoded, &mut buf, 32), Ok(32));
+                assert_eq!(&buf[..32], bytes);
+            }
+        }
+
+        assert_eq!(
+            TokenEncoding::Base64.decode_tolerant("not valid!", &mut buf, 32),
+            Err(DecodeError::Invalid)
+        );
+    }
+}
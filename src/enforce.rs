@@ -0,0 +1,225 @@
+use crate::{CsrfError, CsrfLayer, CsrfToken, HeaderCheckMode, PolicyRequest};
+use axum_core::body::Body;
+use bytes::Bytes;
+use http::{
+    header::{CONTENT_LENGTH, CONTENT_TYPE},
+    request::Parts,
+    Method, StatusCode,
+};
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+
+/// Result of the layer's CSRF check, added to the request's extensions when enforcement is enabled.
+#[derive(Clone, Debug)]
+pub enum CsrfOutcome {
+    /// The request method is safe so it was not checked.
+    Skipped,
+    /// The authenticity token, or the [`crate::ContentTypePolicy`] for its routes, was verified.
+    /// With [`HeaderCheckMode::Any`] the required header alone is enough, and with a
+    /// [`crate::CsrfPolicy`] the policy passed.
+    Passed,
+    /// The check failed. With [`crate::CsrfEnforcement::ReportOnly`] the request was forwarded anyway.
+    Failed(CsrfError),
+}
+
+impl CsrfOutcome {
+    /// Returns true if the check failed.
+    pub fn is_failed(&self) -> bool {
+        matches!(self, CsrfOutcome::Failed(_))
+    }
+}
+
+/// Checks the authenticity token of unsafe requests, returning the body so it can be passed on.
+///
+/// Fails with the status to answer with when the body had to be read and could not be, so the request
+/// is never forwarded without its body.
+pub(crate) async fn check(
+    layer: &CsrfLayer,
+    token: &CsrfToken,
+    parts: &Parts,
+    body: Body,
+) -> Result<(CsrfOutcome, Body), StatusCode> {
+    let query_route = layer
+        .query_token_routes
+        .iter()
+        .any(|path| path == parts.uri.path());
+
+    if is_safe_method(&parts.method) && !query_route {
+        return Ok((CsrfOutcome::Skipped, body));
+    }
+
+    if let Some(policy) = &layer.content_type_policy {
+        if !query_route && policy.applies_to(parts.uri.path()) {
+            return Ok((outcome(layer, token, parts, policy.check(parts)), body));
+        }
+    }
+
+    if let Some((header, mode)) = &layer.required_header {
+        match (header.check(parts), mode) {
+            (Ok(()), HeaderCheckMode::Any) => {
+                return Ok((outcome(layer, token, parts, Ok(())), body))
+            }
+            (Err(err), HeaderCheckMode::All) => {
+                return Ok((outcome(layer, token, parts, Err(err)), body))
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(policy) = &layer.policy {
+        // Only buffer the body when the policy checks the authenticity token.
+        let (form_token, body) = if policy.needs_token() {
+            submitted_token(layer, token, parts, body, query_route).await?
+        } else {
+            (None, body)
+        };
+        let request = PolicyRequest {
+            parts,
+            token,
+            authenticity_token: form_token.as_deref(),
+        };
+
+        return Ok((outcome(layer, token, parts, policy.check(&request)), body));
+    }
+
+    let (form_token, body) = submitted_token(layer, token, parts, body, query_route).await?;
+    let result = match form_token {
+        Some(form_token) => token.check(&form_token),
+        None => Err(CsrfError::MissingToken),
+    };
+
+    Ok((outcome(layer, token, parts, result), body))
+}
+
+/// Reads the authenticity token from the header, the query string of query routes, or the body.
+async fn submitted_token(
+    layer: &CsrfLayer,
+    token: &CsrfToken,
+    parts: &Parts,
+    body: Body,
+    query_route: bool,
+) -> Result<(Option<String>, Body), StatusCode> {
+    if let Some(value) = parts.headers.get(&layer.header_name) {
+        Ok((value.to_str().ok().map(str::to_owned), body))
+    } else if let Some(query_token) = query_route.then(|| token.query_token(&parts.uri)).flatten() {
+        Ok((Some(query_token), body))
+    } else {
+        form_token(layer, token, parts, body).await
+    }
+}
+
+/// Turns the check's result into its outcome, reporting failures.
+///
+/// The Token is checked without [`CsrfToken::verify`] so every outcome is only recorded here once.
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+fn outcome(
+    layer: &CsrfLayer,
+    token: &CsrfToken,
+    parts: &Parts,
+    result: Result<(), CsrfError>,
+) -> CsrfOutcome {
+    #[cfg(feature = "metrics")]
+    match &result {
+        Ok(()) => crate::stats::verify_passed(token),
+        Err(err) => crate::stats::verify_failed(token, err.code()),
+    }
+
+    match result {
+        Ok(()) => CsrfOutcome::Passed,
+        Err(err) => {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(reason = err.code(), enforcement = ?layer.enforcement, "CSRF check failed");
+
+            if let Some(on_failure) = &layer.on_failure {
+                on_failure(parts, &err);
+            }
+
+            CsrfOutcome::Failed(err)
+        }
+    }
+}
+
+fn is_safe_method(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    )
+}
+
+/// Kind of body the token can be read from.
+enum BodyKind {
+    Form,
+    #[cfg(feature = "json")]
+    Json,
+}
+
+/// Reads the token from an urlencoded form or JSON body if it has a Content-Length within the limit.
+///
+/// Fails with `413 Payload Too Large` when the body is longer than its Content-Length said,
+/// or `400 Bad Request` when it could not be read.
+async fn form_token(
+    layer: &CsrfLayer,
+    token: &CsrfToken,
+    parts: &Parts,
+    body: Body,
+) -> Result<(Option<String>, Body), StatusCode> {
+    let kind = parts
+        .headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .and_then(|mime| body_kind(mime.trim()));
+
+    let within_limit = parts
+        .headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok())
+        .is_some_and(|len| len <= layer.body_limit);
+
+    let Some(kind) = kind.filter(|_| within_limit) else {
+        return Ok((None, body));
+    };
+
+    let bytes = match Limited::new(body, layer.body_limit).collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(err) if err.is::<LengthLimitError>() => return Err(StatusCode::PAYLOAD_TOO_LARGE),
+        Err(_) => return Err(StatusCode::BAD_REQUEST),
+    };
+
+    let field_name = &token.config.inner.field_name;
+    let form_token = match kind {
+        BodyKind::Form => find_field(&bytes, field_name),
+        #[cfg(feature = "json")]
+        BodyKind::Json => {
+            crate::json::find_field(&bytes, layer.json_field.as_deref().unwrap_or(field_name))
+        }
+    };
+
+    Ok((form_token, Body::from(bytes)))
+}
+
+fn body_kind(mime: &str) -> Option<BodyKind> {
+    if mime.eq_ignore_ascii_case("application/x-www-form-urlencoded") {
+        return Some(BodyKind::Form);
+    }
+
+    #[cfg(feature = "json")]
+    {
+        let (kind, subtype) = mime.split_once('/')?;
+        let subtype = subtype.to_ascii_lowercase();
+
+        if kind.eq_ignore_ascii_case("application")
+            && (subtype == "json" || subtype.ends_with("+json"))
+        {
+            return Some(BodyKind::Json);
+        }
+    }
+
+    None
+}
+
+fn find_field(bytes: &Bytes, name: &str) -> Option<String> {
+    form_urlencoded::parse(bytes)
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
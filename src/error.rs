@@ -1,13 +1,108 @@
 use thiserror::Error;
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum CsrfError {
-    #[error("Token could not be hashed.")]
-    PasswordHash,
+    /// The authenticity token could not be decoded with the configured [`crate::TokenEncoding`].
+    #[error("Authenticity Token could not be decoded.")]
+    MalformedToken,
+    /// The decoded authenticity token is not the length of a hash.
+    #[error("Authenticity Token is {found} bytes long, expected {expected}.")]
+    TokenLength { expected: usize, found: usize },
+    /// The authenticity token was made with a different [`crate::TokenMac`] than the configured one.
+    #[error("Authenticity Token was made with a different algorithm.")]
+    Algorithm,
+    /// No authenticity token was sent with the request.
+    #[error("No Authenticity Token was sent.")]
+    MissingToken,
+    /// The request had no CSRF cookie so the authenticity token can not match.
+    #[error("CSRF cookie was not sent.")]
+    CookieMissing,
+    /// The CSRF cookie could not be decrypted or its signature was invalid.
+    #[error("CSRF cookie could not be decrypted or verified.")]
+    CookieInvalid,
+    /// More than one CSRF cookie was sent and [`crate::DuplicateCookiePolicy::Reject`] is set.
+    #[error("More than one CSRF cookie was sent.")]
+    DuplicateCookie,
+    /// The authenticity token's hash does not match the cookie's Token.
     #[error("Verification Failed.")]
     Verify,
-    #[error("Could not Encode Salt.")]
-    Salt,
-    #[error("Could not Hash Token.")]
-    Token,
+    /// The Token is older than the configured lifespan.
+    #[error("Token has expired.")]
+    Expired,
+    /// The request's Origin does not match the expected origin.
+    #[error("Request Origin does not match.")]
+    OriginMismatch,
+    /// The request's Content-Type is not allowed by the [`crate::ContentTypePolicy`].
+    #[error("Request Content-Type is not allowed.")]
+    ContentType,
+    /// A required request header was not sent or did not have the expected value.
+    #[error("Required request header was not sent.")]
+    MissingHeader,
+    /// The request's `Sec-Fetch-Site` header shows it was sent from another site, or it was not sent.
+    #[error("Request was sent from another site.")]
+    CrossSite,
+    /// A [`crate::CsrfPolicy`] negated with [`crate::CsrfPolicy::not`] passed.
+    #[error("Request was rejected by the CSRF policy.")]
+    Policy,
+    /// The Token could not be read from or stored within the `tower-sessions` Session.
+    #[cfg(feature = "tower-sessions")]
+    #[error("Could not access the Session.")]
+    Session,
+}
+
+impl CsrfError {
+    /// Returns true if the error was caused by the request rather than the server's configuration.
+    ///
+    /// Client errors should be answered with a `403 Forbidden` and server errors with a `500 Internal Server Error`.
+    pub fn is_client_error(&self) -> bool {
+        #[cfg(feature = "tower-sessions")]
+        if matches!(self, CsrfError::Session) {
+            return false;
+        }
+
+        true
+    }
+
+    /// Returns a stable code for the error that is safe to send within API responses or use as a metric label.
+    pub fn code(&self) -> &'static str {
+        match self {
+            CsrfError::MalformedToken => "malformed_token",
+            CsrfError::TokenLength { .. } => "token_length",
+            CsrfError::Algorithm => "algorithm_mismatch",
+            CsrfError::MissingToken => "missing_token",
+            CsrfError::CookieMissing => "cookie_missing",
+            CsrfError::CookieInvalid => "cookie_invalid",
+            CsrfError::DuplicateCookie => "duplicate_cookie",
+            CsrfError::Verify => "token_mismatch",
+            CsrfError::Expired => "token_expired",
+            CsrfError::OriginMismatch => "origin_mismatch",
+            CsrfError::ContentType => "content_type",
+            CsrfError::MissingHeader => "missing_header",
+            CsrfError::CrossSite => "cross_site",
+            CsrfError::Policy => "policy_rejected",
+            #[cfg(feature = "tower-sessions")]
+            CsrfError::Session => "session_error",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_errors() {
+        assert!(CsrfError::Verify.is_client_error());
+        assert!(CsrfError::MissingToken.is_client_error());
+        #[cfg(feature = "tower-sessions")]
+        assert!(!CsrfError::Session.is_client_error());
+
+        assert_eq!(CsrfError::Verify.code(), "token_mismatch");
+        assert_eq!(CsrfError::CookieInvalid.code(), "cookie_invalid");
+        assert_eq!(
+            CsrfError::MalformedToken.to_string(),
+            "Authenticity Token could not be decoded."
+        );
+    }
 }
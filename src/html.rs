@@ -0,0 +1,108 @@
+use std::fmt::{self, Display, Write};
+
+/// Hidden form input containing the authenticity token.
+///
+/// Created by [`crate::CsrfToken::hidden_input`]. Its [`Display`] output is already
+/// HTML escaped so it can be written into a template as is.
+#[derive(Clone, Debug)]
+pub struct CsrfHiddenInput {
+    pub(crate) field_name: String,
+    pub(crate) authenticity_token: String,
+}
+
+/// `<meta>` tags containing the authenticity token and the form field name.
+///
+/// Created by [`crate::CsrfToken::meta_tag`]. Renders a `csrf-param` tag holding the
+/// configured field name followed by a `csrf-token` tag holding the authenticity token.
+#[derive(Clone, Debug)]
+pub struct CsrfMetaTag {
+    pub(crate) field_name: String,
+    pub(crate) authenticity_token: String,
+}
+
+impl Display for CsrfHiddenInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(r#"<input type="hidden" name=""#)?;
+        escape_html(f, &self.field_name)?;
+        f.write_str(r#"" value=""#)?;
+        escape_html(f, &self.authenticity_token)?;
+        f.write_str(r#""/>"#)
+    }
+}
+
+impl Display for CsrfMetaTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(r#"<meta name="csrf-param" content=""#)?;
+        escape_html(f, &self.field_name)?;
+        f.write_str(r#""/><meta name="csrf-token" content=""#)?;
+        escape_html(f, &self.authenticity_token)?;
+        f.write_str(r#""/>"#)
+    }
+}
+
+/// Writes the value escaping the characters that are unsafe within HTML text and attributes.
+pub(crate) fn escape_html<W: Write>(w: &mut W, value: &str) -> fmt::Result {
+    let mut last = 0;
+
+    for (i, c) in value.char_indices() {
+        let escaped = match c {
+            '&' => "&amp;",
+            '<' => "&lt;",
+            '>' => "&gt;",
+            '"' => "&quot;",
+            '\'' => "&#x27;",
+            _ => continue,
+        };
+
+        w.write_str(&value[last..i])?;
+        w.write_str(escaped)?;
+        last = i + 1;
+    }
+
+    w.write_str(&value[last..])
+}
+
+#[cfg(feature = "askama")]
+impl askama::filters::HtmlSafe for CsrfHiddenInput {}
+
+#[cfg(feature = "askama")]
+impl askama::filters::HtmlSafe for CsrfMetaTag {}
+
+#[cfg(feature = "maud")]
+impl maud::Render for CsrfHiddenInput {
+    fn render_to(&self, buffer: &mut String) {
+        let _ = write!(buffer, "{self}");
+    }
+}
+
+#[cfg(feature = "maud")]
+impl maud::Render for CsrfMetaTag {
+    fn render_to(&self, buffer: &mut String) {
+        let _ = write!(buffer, "{self}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn escape(value: &str) -> String {
+        let mut escaped = String::new();
+        escape_html(&mut escaped, value).unwrap();
+        escaped
+    }
+
+    #[test]
+    fn escape_html_escapes_unsafe_characters() {
+        assert_eq!(
+            escape(r#"<a href="x">'&'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&#x27;&amp;&#x27;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn escape_html_keeps_safe_text() {
+        assert_eq!(escape("abc+/=ÿ"), "abc+/=ÿ");
+        assert_eq!(escape(""), "");
+    }
+}
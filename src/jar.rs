@@ -0,0 +1,179 @@
+use crate::{
+    cookies::{read_token, CookieSource, CookiesExt},
+    CookieMode, CsrfConfig, CsrfToken,
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar, PrivateCookieJar, SignedCookieJar};
+use std::borrow::Cow;
+
+impl CookieSource for CookieJar {
+    fn cookie_values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = Cow<'a, str>> + 'a {
+        self.get(name)
+            .map(|cookie| Cow::Borrowed(cookie.value()))
+            .into_iter()
+    }
+}
+
+impl<K> CookieSource for PrivateCookieJar<K> {
+    fn cookie_values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = Cow<'a, str>> + 'a {
+        self.get(name)
+            .map(|cookie| Cow::Owned(cookie.value().to_owned()))
+            .into_iter()
+    }
+}
+
+impl<K> CookieSource for SignedCookieJar<K> {
+    fn cookie_values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = Cow<'a, str>> + 'a {
+        self.get(name)
+            .map(|cookie| Cow::Owned(cookie.value().to_owned()))
+            .into_iter()
+    }
+}
+
+impl CsrfToken {
+    /// Loads the Token from an `axum_extra` [`CookieJar`] or creates a new one if the cookie does not exist.
+    ///
+    /// The cookie is decoded with the configured [`CookieMode`], so this works with plain, signed and private cookies.
+    /// Jars keep a single cookie per name, so duplicate CSRF cookies are only reported when the [`CsrfToken`]
+    /// is extracted from the request itself.
+    pub fn from_jar(jar: &CookieJar, config: CsrfConfig) -> Self {
+        Self::from_cookies(jar, true, config)
+    }
+
+    /// Loads the Token from an `axum_extra` [`PrivateCookieJar`] or creates a new one if the cookie does not exist.
+    ///
+    /// The jar's key decrypts the cookie instead of the one within the [`CsrfConfig`], and cookies it could not
+    /// decrypt are dropped by the jar so they give a [`crate::TokenSource::Fresh`] Token.
+    pub fn from_private_jar<K>(jar: &PrivateCookieJar<K>, config: CsrfConfig) -> Self {
+        Self::from_cookies(jar, false, config)
+    }
+
+    /// Loads the Token from an `axum_extra` [`SignedCookieJar`] or creates a new one if the cookie does not exist.
+    ///
+    /// The jar's key verifies the cookie instead of the one within the [`CsrfConfig`], and cookies it could not
+    /// verify are dropped by the jar so they give a [`crate::TokenSource::Fresh`] Token.
+    pub fn from_signed_jar<K>(jar: &SignedCookieJar<K>, config: CsrfConfig) -> Self {
+        Self::from_cookies(jar, false, config)
+    }
+
+    /// Reads the cookie the same way as from the request's headers, decoding it with the configured
+    /// [`CookieMode`] unless the jar already did.
+    fn from_cookies(cookies: &impl CookieSource, decode: bool, config: CsrfConfig) -> Self {
+        let mode = if decode {
+            &config.inner.cookie_mode
+        } else {
+            &CookieMode::Plain
+        };
+        let (token, source) = read_token(&config, cookies, mode);
+        Self::load(token, source, config, None)
+    }
+
+    /// Adds the Token's cookie to an `axum_extra` [`CookieJar`], signed or encrypted with the configured [`CookieMode`].
+    ///
+    /// Return the jar instead of the Token so only one Set-Cookie header gets sent.
+    pub fn add_to_jar(&self, jar: CookieJar) -> CookieJar {
+        let mut encoded = cookie::CookieJar::new();
+        encoded.add_cookie(self.build_cookie(), &self.config.inner.cookie_mode);
+
+        match encoded.get(&self.config.prefixed_cookie_name()) {
+            Some(cookie) => jar.add(Cookie::clone(cookie)),
+            None => jar,
+        }
+    }
+
+    /// Adds the Token's cookie to an `axum_extra` [`PrivateCookieJar`] which will encrypt it.
+    ///
+    /// The jar's key is used instead of the configured [`CookieMode`], so for the layer or the [`CsrfToken`]
+    /// extractor to read the cookie back the [`CsrfConfig`] must use [`CookieMode::Private`] with the same key.
+    ///
+    /// Return the jar instead of the Token so only one Set-Cookie header gets sent.
+    pub fn add_to_private_jar<K>(&self, jar: PrivateCookieJar<K>) -> PrivateCookieJar<K> {
+        jar.add(self.build_cookie())
+    }
+
+    /// Adds the Token's cookie to an `axum_extra` [`SignedCookieJar`] which will sign it.
+    ///
+    /// The jar's key is used instead of the configured [`CookieMode`], so for the layer or the [`CsrfToken`]
+    /// extractor to read the cookie back the [`CsrfConfig`] must use [`CookieMode::Signed`] with the same key.
+    ///
+    /// Return the jar instead of the Token so only one Set-Cookie header gets sent.
+    pub fn add_to_signed_jar<K>(&self, jar: SignedCookieJar<K>) -> SignedCookieJar<K> {
+        jar.add(self.build_cookie())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Key, TokenSource};
+    use axum_core::response::IntoResponse;
+    use http::{
+        header::{COOKIE, SET_COOKIE},
+        HeaderMap, HeaderValue,
+    };
+
+    /// Turns the Set-Cookie headers of a jar returned as a response into the next request's Cookie headers.
+    fn request_headers(response: impl IntoResponse) -> HeaderMap {
+        let response = response.into_response();
+        let mut headers = HeaderMap::new();
+
+        for set_cookie in response.headers().get_all(SET_COOKIE) {
+            let pair = set_cookie.to_str().unwrap().split(';').next().unwrap();
+            headers.append(COOKIE, HeaderValue::from_str(pair).unwrap());
+        }
+
+        headers
+    }
+
+    #[test]
+    fn jar_round_trips_every_cookie_mode() {
+        let key = Key::generate();
+
+        for mode in [
+            CookieMode::Plain,
+            CookieMode::Signed(key.clone()),
+            CookieMode::Private(key.clone()),
+        ] {
+            let config = CsrfConfig::default().with_cookie_mode(mode);
+            let token = CsrfToken::from_jar(&CookieJar::new(), config.clone());
+            let headers = request_headers(token.add_to_jar(CookieJar::new()));
+
+            // The extractor reads the cookie the jar wrote, and the jar reads it back.
+            let (value, source) = crate::cookies::get_token(&config, &headers);
+            assert_eq!(source, TokenSource::Cookie);
+            assert_eq!(value.as_deref(), Some(token.token.as_str()));
+
+            let loaded = CsrfToken::from_jar(&CookieJar::from_headers(&headers), config);
+            assert_eq!(loaded.source(), TokenSource::Cookie);
+            assert_eq!(loaded.token, token.token);
+        }
+    }
+
+    #[test]
+    fn jar_reports_invalid_cookie() {
+        let jar = CookieJar::new().add(Cookie::new("Csrf_Token", "tampered"));
+
+        assert_eq!(
+            CsrfToken::from_jar(&jar, CsrfConfig::default()).source(),
+            TokenSource::CookieInvalid
+        );
+    }
+
+    #[test]
+    fn private_jar_round_trips() {
+        let key = Key::generate();
+        let config = CsrfConfig::default().with_cookie_mode(CookieMode::Private(key.clone()));
+        let token =
+            CsrfToken::from_private_jar(&PrivateCookieJar::new(key.clone()), config.clone());
+        let headers = request_headers(token.add_to_private_jar(PrivateCookieJar::new(key.clone())));
+
+        assert_eq!(
+            crate::cookies::get_token(&config, &headers).0.as_deref(),
+            Some(token.token.as_str())
+        );
+
+        let loaded =
+            CsrfToken::from_private_jar(&PrivateCookieJar::from_headers(&headers, key), config);
+        assert_eq!(loaded.source(), TokenSource::Cookie);
+        assert_eq!(loaded.token, token.token);
+    }
+}
@@ -0,0 +1,83 @@
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, Visitor};
+use std::fmt;
+
+/// Reads a top level string field from a JSON object body, skipping every other value without keeping it.
+pub(crate) fn find_field(bytes: &[u8], name: &str) -> Option<String> {
+    let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+    let value = Field(name).deserialize(&mut deserializer).ok()?;
+
+    deserializer.end().ok()?;
+    value
+}
+
+struct Field<'a>(&'a str);
+
+impl<'de> DeserializeSeed<'de> for Field<'_> {
+    type Value = Option<String>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Field<'_> {
+    type Value = Option<String>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a JSON object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut value = None;
+
+        while let Some(key) = map.next_key::<String>()? {
+            if value.is_none() && key == self.0 {
+                // Anything other than a string is treated as a missing token.
+                value = map
+                    .next_value::<serde_json::Value>()?
+                    .as_str()
+                    .map(str::to_owned);
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_top_level_string() {
+        let body = br#"{"nested": {"authenticity_token": "no"}, "authenticity_token": "yes", "n": [1, 2]}"#;
+
+        assert_eq!(
+            find_field(body, "authenticity_token"),
+            Some("yes".to_owned())
+        );
+        assert_eq!(find_field(body, "missing"), None);
+    }
+
+    #[test]
+    fn ignores_other_values() {
+        assert_eq!(
+            find_field(br#"{"authenticity_token": 1}"#, "authenticity_token"),
+            None
+        );
+        assert_eq!(
+            find_field(br#"["authenticity_token"]"#, "authenticity_token"),
+            None
+        );
+        assert_eq!(
+            find_field(br#"{"authenticity_token": "a"} x"#, "authenticity_token"),
+            None
+        );
+        assert_eq!(
+            find_field(br#"{"authenticity_token": "ab"}"#, "authenticity_token"),
+            Some("ab".to_owned())
+        );
+    }
+}
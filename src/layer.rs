@@ -1,16 +1,337 @@
-use crate::{AxumCsrfService, CsrfConfig};
+use crate::{
+    AxumCsrfService, ContentTypePolicy, CsrfConfig, CsrfError, CsrfPolicy, CsrfViolationHandler,
+    HeaderCheckMode, RequiredHeader,
+};
+use http::{request::Parts, HeaderName};
+use std::sync::Arc;
 use tower_layer::Layer;
 
+/// Callback used to report failed CSRF checks.
+pub(crate) type FailureCallback = Arc<dyn Fn(&Parts, &CsrfError) + Send + Sync>;
+
+/// How the layer handles unsafe requests (`POST`, `PUT`, `PATCH`, `DELETE`, ...).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CsrfEnforcement {
+    /// The layer only provides the [`crate::CsrfToken`] and handlers do their own verification.
+    #[default]
+    Off,
+    /// Failed checks are reported but the request is still forwarded.
+    /// Useful to measure the impact before turning on [`CsrfEnforcement::Enforce`].
+    ReportOnly,
+    /// Failed checks are reported and the request is rejected with `403 Forbidden`.
+    Enforce,
+}
+
 /// CSRF layer struct used to pass key and CsrfConfig around.
 #[derive(Clone)]
 pub struct CsrfLayer {
     pub(crate) config: CsrfConfig,
+    pub(crate) inject_forms: bool,
+    pub(crate) enforcement: CsrfEnforcement,
+    pub(crate) header_name: HeaderName,
+    pub(crate) body_limit: usize,
+    pub(crate) on_failure: Option<FailureCallback>,
+    pub(crate) violation_handler: Option<Arc<dyn CsrfViolationHandler>>,
+    pub(crate) client_ip_header: Option<HeaderName>,
+    pub(crate) query_token_routes: Arc<[String]>,
+    pub(crate) content_type_policy: Option<ContentTypePolicy>,
+    pub(crate) required_header: Option<(RequiredHeader, HeaderCheckMode)>,
+    pub(crate) policy: Option<Arc<dyn CsrfPolicy>>,
+    #[cfg(feature = "json")]
+    pub(crate) json_field: Option<Arc<str>>,
 }
 
 impl CsrfLayer {
     /// Creates the CSRF Protection Layer.
     pub fn new(config: CsrfConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            inject_forms: false,
+            enforcement: CsrfEnforcement::Off,
+            header_name: HeaderName::from_static("x-csrf-token"),
+            body_limit: 64 * 1024,
+            on_failure: None,
+            violation_handler: None,
+            client_ip_header: None,
+            query_token_routes: Arc::new([]),
+            content_type_policy: None,
+            required_header: None,
+            policy: None,
+            #[cfg(feature = "json")]
+            json_field: None,
+        }
+    }
+
+    /// Set's if the hidden authenticity token input gets injected into html forms.
+    ///
+    /// When enabled every `text/html` response is scanned as it streams and the hidden input
+    /// is added right after each `<form method="post">` tag whose action posts back to the same host.
+    /// Compressed responses are left untouched, so add this layer inside any compression layer.
+    /// The Content-Length header is dropped from rewritten html responses, even complete ones, as their
+    /// final length is only known once streamed, so they are sent chunked. The Token's cookie is added
+    /// if the handler did not return the Token itself. It is disabled by default.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::{CsrfConfig, CsrfLayer};
+    ///
+    /// let layer = CsrfLayer::new(CsrfConfig::default()).with_form_injection(true);
+    /// ```
+    ///
+    #[must_use]
+    pub fn with_form_injection(mut self, enable: bool) -> Self {
+        self.inject_forms = enable;
+        self
+    }
+
+    /// Set's if the layer verifies the authenticity token of unsafe requests itself.
+    ///
+    /// The token is read from the configured header first, then from the configured field name
+    /// of an `application/x-www-form-urlencoded` body. The outcome is added to the request's
+    /// extensions as a [`crate::CsrfOutcome`]. Default is [`CsrfEnforcement::Off`].
+    ///
+    /// `multipart/form-data` bodies are not parsed, so forms uploading files must send the token
+    /// within the header. A body that was read but failed part way is answered with
+    /// `413 Payload Too Large` or `400 Bad Request` instead of being passed on.
+    ///
+    /// With the `tower-sessions` feature, a Token stored within the request's Session is checked
+    /// instead of the cookie's, so forms rendered from a `SessionCsrfToken` pass. This
+    /// requires the `SessionManagerLayer` to be added after, so it wraps this layer.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::{CsrfConfig, CsrfEnforcement, CsrfLayer};
+    ///
+    /// let layer = CsrfLayer::new(CsrfConfig::default()).with_enforcement(CsrfEnforcement::ReportOnly);
+    /// ```
+    ///
+    #[must_use]
+    pub fn with_enforcement(mut self, enforcement: CsrfEnforcement) -> Self {
+        self.enforcement = enforcement;
+        self
+    }
+
+    /// Set's the header the authenticity token is read from. Default is "x-csrf-token".
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::{CsrfConfig, CsrfLayer};
+    ///
+    /// let layer = CsrfLayer::new(CsrfConfig::default()).with_header_name("x-xsrf-token");
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name is not a valid header name.
+    #[must_use]
+    pub fn with_header_name(mut self, name: &str) -> Self {
+        self.header_name = HeaderName::try_from(name).expect("invalid CSRF header name");
+        self
+    }
+
+    /// Set's the largest form or JSON body in bytes that will be read looking for the authenticity token.
+    ///
+    /// Bodies without a Content-Length or larger than this are not read, so the token must be sent
+    /// within the header instead. A body longer than its Content-Length said is answered with
+    /// `413 Payload Too Large`. Default is 64 KiB.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::{CsrfConfig, CsrfLayer};
+    ///
+    /// let layer = CsrfLayer::new(CsrfConfig::default()).with_body_limit(16 * 1024);
+    /// ```
+    ///
+    #[must_use]
+    pub fn with_body_limit(mut self, limit: usize) -> Self {
+        self.body_limit = limit;
+        self
+    }
+
+    /// Set's a callback that is called for every failed check.
+    ///
+    /// Called in both [`CsrfEnforcement::ReportOnly`] and [`CsrfEnforcement::Enforce`] so
+    /// failures can be logged or counted before enforcement is turned on.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::{CsrfConfig, CsrfLayer};
+    ///
+    /// let layer = CsrfLayer::new(CsrfConfig::default()).with_on_failure(|parts, err| {
+    ///     eprintln!("CSRF check failed for {} {}: {err}", parts.method, parts.uri.path());
+    /// });
+    /// ```
+    ///
+    #[must_use]
+    pub fn with_on_failure<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&Parts, &CsrfError) + Send + Sync + 'static,
+    {
+        self.on_failure = Some(Arc::new(callback));
+        self
+    }
+
+    /// Set's the handler that is called with the details of every failed check.
+    ///
+    /// Unlike [`CsrfLayer::with_on_failure`] the handler is async and can replace the
+    /// `403 Forbidden` response sent with [`CsrfEnforcement::Enforce`].
+    ///
+    /// # Examples
+    /// ```rust,ignore
+    /// use axum_csrf::{CsrfConfig, CsrfLayer};
+    ///
+    /// let layer = CsrfLayer::new(CsrfConfig::default()).with_violation_handler(SiemReporter::new());
+    /// ```
+    ///
+    #[must_use]
+    pub fn with_violation_handler(mut self, handler: impl CsrfViolationHandler) -> Self {
+        self.violation_handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// Set's the header the client IP is read from for [`crate::CsrfViolation`]s.
+    ///
+    /// When the header contains a list, like `X-Forwarded-For`, the first entry is used.
+    /// Only set this when a trusted proxy sets the header. Default is none.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::{CsrfConfig, CsrfLayer};
+    ///
+    /// let layer = CsrfLayer::new(CsrfConfig::default()).with_client_ip_header("x-forwarded-for");
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name is not a valid header name.
+    #[must_use]
+    pub fn with_client_ip_header(mut self, name: &str) -> Self {
+        self.client_ip_header =
+            Some(HeaderName::try_from(name).expect("invalid client IP header name"));
+        self
+    }
+}
+
+impl CsrfLayer {
+    /// Set's the paths whose requests may carry the authenticity token within the query string.
+    ///
+    /// Requests to these paths are checked whatever their method, including `GET`, so legacy links like
+    /// `/logout?authenticity_token=...` can be protected. The query parameter is named after the configured
+    /// field name and can be added with [`crate::CsrfToken::url_with_token`]. Paths must match exactly.
+    /// Only used when enforcement is enabled. Tokens within urls can leak through logs and the Referer header,
+    /// so move these links to forms when you can.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::{CsrfConfig, CsrfEnforcement, CsrfLayer};
+    ///
+    /// let layer = CsrfLayer::new(CsrfConfig::default())
+    ///     .with_enforcement(CsrfEnforcement::Enforce)
+    ///     .with_query_token_routes(["/logout"]);
+    /// ```
+    ///
+    #[must_use]
+    pub fn with_query_token_routes<I>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.query_token_routes = paths.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set's the policy checking the Content-Type of unsafe requests to its routes instead of the authenticity token.
+    ///
+    /// Requests with a Content-Type outside the allowed list fail with [`CsrfError::ContentType`], and
+    /// those without the policy's required header with [`CsrfError::MissingHeader`]. It is also checked
+    /// instead of a [`CsrfPolicy`] on its routes, so set it after [`CsrfLayer::with_policy`].
+    /// Only used when enforcement is enabled.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::{ContentTypePolicy, CsrfConfig, CsrfEnforcement, CsrfLayer};
+    ///
+    /// let layer = CsrfLayer::new(CsrfConfig::default())
+    ///     .with_enforcement(CsrfEnforcement::Enforce)
+    ///     .with_content_type_policy(ContentTypePolicy::new(["application/json"]).with_routes(["/api/"]));
+    /// ```
+    #[must_use]
+    pub fn with_content_type_policy(mut self, policy: ContentTypePolicy) -> Self {
+        self.content_type_policy = Some(policy);
+        self
+    }
+
+    /// Set's a header unsafe requests must carry and how it combines with the authenticity token.
+    ///
+    /// With [`HeaderCheckMode::All`] requests without the header fail with [`CsrfError::MissingHeader`]
+    /// before the token is checked. With [`HeaderCheckMode::Any`] requests with the header pass without
+    /// a token and the body is not read. A [`CsrfPolicy`] is combined with the header the same way as the
+    /// token, so set it after [`CsrfLayer::with_policy`]. Only used when enforcement is enabled.
+    ///
+    /// Routes of the layer's [`ContentTypePolicy`] are checked by that policy alone, so this header is
+    /// not required there. Set it with [`ContentTypePolicy::with_required_header`] as well to require it.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::{CsrfConfig, CsrfEnforcement, CsrfLayer, HeaderCheckMode, RequiredHeader};
+    ///
+    /// let layer = CsrfLayer::new(CsrfConfig::default())
+    ///     .with_enforcement(CsrfEnforcement::Enforce)
+    ///     .with_required_header(RequiredHeader::new("x-requested-with"), HeaderCheckMode::Any);
+    /// ```
+    #[must_use]
+    pub fn with_required_header(mut self, header: RequiredHeader, mode: HeaderCheckMode) -> Self {
+        self.required_header = Some((header, mode));
+        self
+    }
+
+    /// Set's the [`CsrfPolicy`] unsafe requests are checked with instead of only the authenticity token.
+    ///
+    /// The authenticity token is still read from the header, query string or body and handed to the policy,
+    /// which [`crate::policy::Token`] verifies. The body is only read when the policy's
+    /// [`CsrfPolicy::needs_token`] returns true. Only used when enforcement is enabled.
+    ///
+    /// Clears a [`ContentTypePolicy`] or required header set before. Set them after the policy to have
+    /// them checked first, the same way they are before the authenticity token.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::{
+    ///     policy::{FetchMetadata, Origin, Token},
+    ///     CsrfConfig, CsrfEnforcement, CsrfLayer, CsrfPolicy,
+    /// };
+    ///
+    /// let layer = CsrfLayer::new(CsrfConfig::default())
+    ///     .with_enforcement(CsrfEnforcement::Enforce)
+    ///     .with_policy(FetchMetadata::new().or(Origin::new().and(Token)));
+    /// ```
+    #[must_use]
+    pub fn with_policy(mut self, policy: impl CsrfPolicy) -> Self {
+        self.content_type_policy = None;
+        self.required_header = None;
+        self.policy = Some(Arc::new(policy));
+        self
+    }
+}
+
+#[cfg(feature = "json")]
+impl CsrfLayer {
+    /// Set's the top level field of `application/json` bodies the authenticity token is read from.
+    ///
+    /// Defaults to the configured field name. The body is limited by [`CsrfLayer::with_body_limit`]
+    /// and passed on untouched so `Json<T>` extractors still work.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::{CsrfConfig, CsrfLayer};
+    ///
+    /// let layer = CsrfLayer::new(CsrfConfig::default()).with_json_field("authenticityToken");
+    /// ```
+    ///
+    #[must_use]
+    pub fn with_json_field(mut self, name: &str) -> Self {
+        self.json_field = Some(name.into());
+        self
     }
 }
 
@@ -19,7 +340,7 @@ impl<S> Layer<S> for CsrfLayer {
 
     fn layer(&self, inner: S) -> Self::Service {
         AxumCsrfService {
-            config: self.config.clone(),
+            layer: self.clone(),
             inner,
         }
     }
@@ -1,21 +1,69 @@
 #![doc = include_str!("../README.md")]
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 mod config;
+#[cfg(feature = "layer")]
+mod content_type;
+mod encoding;
 mod error;
+mod html;
+#[cfg(feature = "axum-extra")]
+mod jar;
+mod mac;
 mod token;
 
 pub(crate) mod cookies;
 
+#[cfg(feature = "layer")]
+mod enforce;
+#[cfg(feature = "json")]
+mod json;
 #[cfg(feature = "layer")]
 mod layer;
 #[cfg(feature = "layer")]
+pub mod policy;
+#[cfg(feature = "layer")]
+mod required_header;
+#[cfg(feature = "layer")]
+mod rewrite;
+#[cfg(feature = "layer")]
 mod service;
+#[cfg(feature = "tower-sessions")]
+mod session;
+#[cfg(feature = "metrics")]
+mod stats;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+#[cfg(feature = "tracing")]
+mod trace;
+#[cfg(feature = "layer")]
+mod violation;
 
+/// Re-exported to implement [`CsrfViolationHandler`].
+#[cfg(feature = "layer")]
+pub use async_trait::async_trait;
 #[cfg(feature = "layer")]
-pub use layer::CsrfLayer;
+pub use content_type::ContentTypePolicy;
+#[cfg(feature = "layer")]
+pub use enforce::CsrfOutcome;
+#[cfg(feature = "layer")]
+pub use layer::{CsrfEnforcement, CsrfLayer};
+#[cfg(feature = "layer")]
+pub use policy::{CsrfPolicy, PolicyRequest};
+#[cfg(feature = "layer")]
+pub use required_header::{HeaderCheckMode, RequiredHeader};
 #[cfg(feature = "layer")]
 pub(crate) use service::AxumCsrfService;
+#[cfg(feature = "layer")]
+pub use violation::{CsrfViolation, CsrfViolationHandler};
 
-pub use config::{CsrfConfig, Key, SameSite};
+pub use config::{CookieMode, CsrfConfig, DuplicateCookiePolicy, Key, SameSite};
+pub use encoding::TokenEncoding;
 pub use error::CsrfError;
-pub use token::CsrfToken;
+pub use html::{CsrfHiddenInput, CsrfMetaTag};
+#[cfg(feature = "blake3")]
+pub use mac::Blake3;
+pub use mac::{HmacSha256, HmacSha512, TokenMac};
+pub use token::{CsrfToken, TokenSource};
+
+#[cfg(feature = "tower-sessions")]
+pub use session::SessionCsrfToken;
@@ -0,0 +1,124 @@
+use hmac::{Hmac, Mac};
+use sha2::{Sha256, Sha512};
+
+/// Longest MAC a [`TokenMac`] can produce, in bytes.
+pub(crate) const MAX_MAC_LEN: usize = 64;
+
+/// Keyed hash used to turn the cookie's Token into the authenticity token placed within forms.
+///
+/// The identifier is written in front of every authenticity token, so changing the algorithm
+/// makes tokens rendered with the previous one fail with [`crate::CsrfError::Algorithm`].
+///
+/// # Examples
+/// ```rust
+/// use axum_csrf::{CsrfConfig, HmacSha512};
+///
+/// let config = CsrfConfig::default().with_mac::<HmacSha512>();
+/// ```
+pub trait TokenMac: Send + Sync + 'static {
+    /// Creates the MAC keyed with the configured salt.
+    fn new_keyed(salt: &[u8]) -> Self
+    where
+        Self: Sized;
+
+    /// Short identifier written in front of authenticity tokens, must not contain a `.`.
+    fn id(&self) -> &'static str;
+
+    /// Length of the MAC in bytes, at most 64.
+    fn output_len(&self) -> usize;
+
+    /// Writes the MAC of the Token into `out`, which is [`TokenMac::output_len`] bytes long.
+    fn sign(&self, token: &[u8], out: &mut [u8]);
+
+    /// Checks in constant time that `tag` is the MAC of the Token.
+    fn verify(&self, token: &[u8], tag: &[u8]) -> bool;
+}
+
+macro_rules! hmac_token_mac {
+    ($(#[$doc:meta])* $name:ident, $hash:ty, $id:literal, $len:literal) => {
+        $(#[$doc])*
+        #[derive(Clone)]
+        pub struct $name(Hmac<$hash>);
+
+        impl TokenMac for $name {
+            fn new_keyed(salt: &[u8]) -> Self {
+                // HMAC hashes keys longer than its block size and pads shorter ones, so any salt works.
+                Self(Hmac::new_from_slice(salt).expect("HMAC can take a key of any size"))
+            }
+
+            fn id(&self) -> &'static str {
+                $id
+            }
+
+            fn output_len(&self) -> usize {
+                $len
+            }
+
+            fn sign(&self, token: &[u8], out: &mut [u8]) {
+                let mut mac = self.0.clone();
+                mac.update(token);
+                out.copy_from_slice(&mac.finalize().into_bytes());
+            }
+
+            fn verify(&self, token: &[u8], tag: &[u8]) -> bool {
+                let mut mac = self.0.clone();
+                mac.update(token);
+                mac.verify_slice(tag).is_ok()
+            }
+        }
+    };
+}
+
+hmac_token_mac!(
+    /// HMAC-SHA256, the default. Identified as `hs256`.
+    HmacSha256,
+    Sha256,
+    "hs256",
+    32
+);
+
+hmac_token_mac!(
+    /// HMAC-SHA512. Identified as `hs512`.
+    HmacSha512,
+    Sha512,
+    "hs512",
+    64
+);
+
+/// BLAKE3 in keyed mode, with the key derived from the salt. Identified as `b3`.
+#[cfg(feature = "blake3")]
+#[derive(Clone)]
+pub struct Blake3(blake3::Hasher);
+
+#[cfg(feature = "blake3")]
+impl TokenMac for Blake3 {
+    fn new_keyed(salt: &[u8]) -> Self {
+        let key = blake3::derive_key("axum_csrf 2025 authenticity token", salt);
+        Self(blake3::Hasher::new_keyed(&key))
+    }
+
+    fn id(&self) -> &'static str {
+        "b3"
+    }
+
+    fn output_len(&self) -> usize {
+        blake3::OUT_LEN
+    }
+
+    fn sign(&self, token: &[u8], out: &mut [u8]) {
+        let mut hasher = self.0.clone();
+        hasher.update(token);
+        out.copy_from_slice(hasher.finalize().as_bytes());
+    }
+
+    fn verify(&self, token: &[u8], tag: &[u8]) -> bool {
+        let Ok(tag) = <[u8; blake3::OUT_LEN]>::try_from(tag) else {
+            return false;
+        };
+
+        let mut hasher = self.0.clone();
+        hasher.update(token);
+        // Comparing two `Hash`es is constant time.
+        hasher.finalize() == blake3::Hash::from_bytes(tag)
+    }
+}
@@ -0,0 +1,394 @@
+//! Composable checks the layer runs on unsafe requests, set with [`crate::CsrfLayer::with_policy`].
+//!
+//! Policies are combined with [`CsrfPolicy::and`], [`CsrfPolicy::or`], [`CsrfPolicy::not`] and
+//! [`CsrfPolicy::when`], so a router can accept requests the browser marks as same origin, or else
+//! require a matching Origin and a valid authenticity token:
+//!
+//! ```rust
+//! use axum_csrf::{
+//!     policy::{FetchMetadata, Origin, Token},
+//!     CsrfConfig, CsrfEnforcement, CsrfLayer, CsrfPolicy,
+//! };
+//!
+//! let layer = CsrfLayer::new(CsrfConfig::default())
+//!     .with_enforcement(CsrfEnforcement::Enforce)
+//!     .with_policy(FetchMetadata::new().or(Origin::new().and(Token)));
+//! ```
+use crate::{ContentTypePolicy, CsrfError, CsrfToken, RequiredHeader};
+use http::{
+    header::{HOST, ORIGIN, REFERER},
+    request::Parts,
+    HeaderName,
+};
+use std::sync::Arc;
+
+/// A check run by the layer on unsafe requests.
+///
+/// Besides the built-in policies of this module, [`ContentTypePolicy`] and [`RequiredHeader`]
+/// can be used as policies. Their routes and [`crate::HeaderCheckMode`] are ignored, use
+/// [`CsrfPolicy::when`] and [`CsrfPolicy::or`] instead.
+pub trait CsrfPolicy: Send + Sync + 'static {
+    /// Checks the request, returning why it was rejected.
+    fn check(&self, request: &PolicyRequest<'_>) -> Result<(), CsrfError>;
+
+    /// Returns true when [`PolicyRequest::authenticity_token`] is used, so the layer reads it from the
+    /// header, query string or body. The body is left unread otherwise. Default is false.
+    fn needs_token(&self) -> bool {
+        false
+    }
+
+    /// Passes only when both policies pass. `other` is not checked when this one fails.
+    #[must_use]
+    fn and<P: CsrfPolicy>(self, other: P) -> And<Self, P>
+    where
+        Self: Sized,
+    {
+        And(self, other)
+    }
+
+    /// Passes when either policy passes, failing with `other`'s error when both fail.
+    #[must_use]
+    fn or<P: CsrfPolicy>(self, other: P) -> Or<Self, P>
+    where
+        Self: Sized,
+    {
+        Or(self, other)
+    }
+
+    /// Passes when this policy fails, failing with [`CsrfError::Policy`] when it passes.
+    #[must_use]
+    fn not(self) -> Not<Self>
+    where
+        Self: Sized,
+    {
+        Not(self)
+    }
+
+    /// Only checks requests the predicate returns true for, passing every other request.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::{policy::Token, CsrfPolicy};
+    ///
+    /// let policy = Token.when(|parts| !parts.uri.path().starts_with("/webhooks/"));
+    /// ```
+    #[must_use]
+    fn when<F>(self, predicate: F) -> When<Self, F>
+    where
+        Self: Sized,
+        F: Fn(&Parts) -> bool + Send + Sync + 'static,
+    {
+        When(self, predicate)
+    }
+}
+
+impl<P: CsrfPolicy + ?Sized> CsrfPolicy for Arc<P> {
+    fn check(&self, request: &PolicyRequest<'_>) -> Result<(), CsrfError> {
+        (**self).check(request)
+    }
+
+    fn needs_token(&self) -> bool {
+        (**self).needs_token()
+    }
+}
+
+/// The request a [`CsrfPolicy`] checks.
+#[derive(Clone, Copy)]
+pub struct PolicyRequest<'a> {
+    pub(crate) parts: &'a Parts,
+    pub(crate) token: &'a CsrfToken,
+    pub(crate) authenticity_token: Option<&'a str>,
+}
+
+impl<'a> PolicyRequest<'a> {
+    /// The request's method, uri, headers and extensions.
+    pub fn parts(&self) -> &'a Parts {
+        self.parts
+    }
+
+    /// The request's Token.
+    pub fn token(&self) -> &'a CsrfToken {
+        self.token
+    }
+
+    /// The authenticity token sent with the request, read the same way as without a policy.
+    ///
+    /// Always `None` unless [`CsrfPolicy::needs_token`] returns true.
+    pub fn authenticity_token(&self) -> Option<&'a str> {
+        self.authenticity_token
+    }
+}
+
+/// Policy made by [`CsrfPolicy::and`].
+#[derive(Clone, Debug)]
+pub struct And<A, B>(A, B);
+
+impl<A: CsrfPolicy, B: CsrfPolicy> CsrfPolicy for And<A, B> {
+    fn check(&self, request: &PolicyRequest<'_>) -> Result<(), CsrfError> {
+        self.0.check(request)?;
+        self.1.check(request)
+    }
+
+    fn needs_token(&self) -> bool {
+        self.0.needs_token() || self.1.needs_token()
+    }
+}
+
+/// Policy made by [`CsrfPolicy::or`].
+#[derive(Clone, Debug)]
+pub struct Or<A, B>(A, B);
+
+impl<A: CsrfPolicy, B: CsrfPolicy> CsrfPolicy for Or<A, B> {
+    fn check(&self, request: &PolicyRequest<'_>) -> Result<(), CsrfError> {
+        self.0.check(request).or_else(|_| self.1.check(request))
+    }
+
+    fn needs_token(&self) -> bool {
+        self.0.needs_token() || self.1.needs_token()
+    }
+}
+
+/// Policy made by [`CsrfPolicy::not`].
+#[derive(Clone, Debug)]
+pub struct Not<P>(P);
+
+impl<P: CsrfPolicy> CsrfPolicy for Not<P> {
+    fn check(&self, request: &PolicyRequest<'_>) -> Result<(), CsrfError> {
+        match self.0.check(request) {
+            Ok(()) => Err(CsrfError::Policy),
+            Err(_) => Ok(()),
+        }
+    }
+
+    fn needs_token(&self) -> bool {
+        self.0.needs_token()
+    }
+}
+
+/// Policy made by [`CsrfPolicy::when`].
+#[derive(Clone)]
+pub struct When<P, F>(P, F);
+
+impl<P, F> CsrfPolicy for When<P, F>
+where
+    P: CsrfPolicy,
+    F: Fn(&Parts) -> bool + Send + Sync + 'static,
+{
+    fn check(&self, request: &PolicyRequest<'_>) -> Result<(), CsrfError> {
+        if (self.1)(request.parts) {
+            self.0.check(request)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn needs_token(&self) -> bool {
+        self.0.needs_token()
+    }
+}
+
+/// Verifies the authenticity token sent with the request against its Token.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Token;
+
+impl CsrfPolicy for Token {
+    fn check(&self, request: &PolicyRequest<'_>) -> Result<(), CsrfError> {
+        match request.authenticity_token {
+            Some(authenticity_token) => request.token.check(authenticity_token),
+            None => Err(CsrfError::MissingToken),
+        }
+    }
+
+    fn needs_token(&self) -> bool {
+        true
+    }
+}
+
+/// Requires the Origin header, or the Referer when it is missing, to match the request's Host or an allowed origin.
+///
+/// Both the scheme and the authority are compared. The request's own origin uses the scheme of its uri,
+/// or `https` when the uri has none as is the case behind most servers, see [`Origin::with_scheme`].
+/// Fails with [`CsrfError::OriginMismatch`], including when neither header was sent.
+///
+/// # Examples
+/// ```rust
+/// use axum_csrf::policy::Origin;
+///
+/// let policy = Origin::new().with_allowed_origins(["https://app.example.com"]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Origin {
+    allowed: Arc<[String]>,
+    scheme: Arc<str>,
+}
+
+impl Default for Origin {
+    fn default() -> Self {
+        Self {
+            allowed: Arc::new([]),
+            scheme: Arc::from("https"),
+        }
+    }
+}
+
+impl Origin {
+    /// Allows requests from the request's own Host.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set's the scheme of the request's own origin when its uri has none. Default is `https`.
+    ///
+    /// Use `http` only for sites that are not served over TLS, such as during local development.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::policy::Origin;
+    ///
+    /// let policy = Origin::new().with_scheme("http");
+    /// ```
+    ///
+    #[must_use]
+    pub fn with_scheme(mut self, scheme: &str) -> Self {
+        self.scheme = Arc::from(scheme);
+        self
+    }
+
+    /// Set's other origins to allow, written as `scheme://host[:port]` and compared ignoring ASCII case.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::policy::Origin;
+    ///
+    /// let policy = Origin::new().with_allowed_origins(["https://app.example.com"]);
+    /// ```
+    ///
+    #[must_use]
+    pub fn with_allowed_origins<I>(mut self, origins: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.allowed = origins.into_iter().map(Into::into).collect();
+        self
+    }
+}
+
+impl CsrfPolicy for Origin {
+    fn check(&self, request: &PolicyRequest<'_>) -> Result<(), CsrfError> {
+        let parts = request.parts;
+        let header = |name: HeaderName| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+
+        // The Referer is the full url, only its scheme and authority are compared.
+        let origin = header(ORIGIN).or_else(|| {
+            header(REFERER).map(|referer| {
+                let start = referer.find("://").map_or(0, |scheme| scheme + 3);
+                let end = referer[start..]
+                    .find(['/', '?', '#'])
+                    .map_or(referer.len(), |end| start + end);
+                &referer[..end]
+            })
+        });
+
+        let Some(origin) = origin else {
+            return Err(CsrfError::OriginMismatch);
+        };
+
+        if self
+            .allowed
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(origin))
+        {
+            return Ok(());
+        }
+
+        let host =
+            header(HOST).or_else(|| parts.uri.authority().map(|authority| authority.as_str()));
+        let scheme = parts.uri.scheme_str().unwrap_or(&self.scheme);
+
+        match (origin.split_once("://"), host) {
+            (Some((origin_scheme, authority)), Some(host))
+                if origin_scheme.eq_ignore_ascii_case(scheme)
+                    && authority.eq_ignore_ascii_case(host) =>
+            {
+                Ok(())
+            }
+            _ => Err(CsrfError::OriginMismatch),
+        }
+    }
+}
+
+/// Uses the `Sec-Fetch-Site` header browsers send to tell where a request came from.
+///
+/// Requests the browser marks as `same-origin`, or `none` when the user made them directly, pass.
+/// Older browsers do not send the header, so combine it with another policy using [`CsrfPolicy::or`].
+/// Fails with [`CsrfError::CrossSite`].
+///
+/// # Examples
+/// ```rust
+/// use axum_csrf::{
+///     policy::{FetchMetadata, Token},
+///     CsrfPolicy,
+/// };
+///
+/// let policy = FetchMetadata::new().or(Token);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FetchMetadata {
+    same_site: bool,
+}
+
+impl FetchMetadata {
+    /// Allows `same-origin` and `none` requests.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set's if `same-site` requests, such as those from sibling subdomains, also pass. Default is false.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::policy::FetchMetadata;
+    ///
+    /// let policy = FetchMetadata::new().with_same_site(true);
+    /// ```
+    ///
+    #[must_use]
+    pub fn with_same_site(mut self, allow: bool) -> Self {
+        self.same_site = allow;
+        self
+    }
+}
+
+impl CsrfPolicy for FetchMetadata {
+    fn check(&self, request: &PolicyRequest<'_>) -> Result<(), CsrfError> {
+        let site = request
+            .parts
+            .headers
+            .get("sec-fetch-site")
+            .map(|value| value.as_bytes());
+
+        match site {
+            Some(b"same-origin" | b"none") => Ok(()),
+            Some(b"same-site") if self.same_site => Ok(()),
+            _ => Err(CsrfError::CrossSite),
+        }
+    }
+}
+
+impl CsrfPolicy for ContentTypePolicy {
+    fn check(&self, request: &PolicyRequest<'_>) -> Result<(), CsrfError> {
+        ContentTypePolicy::check(self, request.parts)
+    }
+}
+
+impl CsrfPolicy for RequiredHeader {
+    fn check(&self, request: &PolicyRequest<'_>) -> Result<(), CsrfError> {
+        RequiredHeader::check(self, request.parts)
+    }
+}
@@ -0,0 +1,75 @@
+use crate::CsrfError;
+use http::{request::Parts, HeaderName, HeaderValue};
+
+/// Header unsafe requests must carry, set with [`crate::CsrfLayer::with_required_header`].
+///
+/// Browsers only send custom headers cross-origin after a successful CORS preflight, so requiring one
+/// such as `X-Requested-With` is a lightweight defense for requests made by scripts.
+///
+/// # Examples
+/// ```rust
+/// use axum_csrf::RequiredHeader;
+///
+/// let header = RequiredHeader::new("x-requested-with").with_value("XMLHttpRequest");
+/// ```
+#[derive(Clone, Debug)]
+pub struct RequiredHeader {
+    pub(crate) name: HeaderName,
+    pub(crate) value: Option<HeaderValue>,
+}
+
+impl RequiredHeader {
+    /// Requires the header to be present, whatever its value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name is not a valid header name.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: HeaderName::try_from(name).expect("invalid required header name"),
+            value: None,
+        }
+    }
+
+    /// Set's the value the header must have, compared ignoring ASCII case.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::RequiredHeader;
+    ///
+    /// let header = RequiredHeader::new("x-requested-with").with_value("XMLHttpRequest");
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is not a valid header value.
+    #[must_use]
+    pub fn with_value(mut self, value: &str) -> Self {
+        self.value = Some(HeaderValue::try_from(value).expect("invalid required header value"));
+        self
+    }
+
+    /// Checks the header is present with the expected value.
+    pub(crate) fn check(&self, parts: &Parts) -> Result<(), CsrfError> {
+        let Some(found) = parts.headers.get(&self.name) else {
+            return Err(CsrfError::MissingHeader);
+        };
+
+        match &self.value {
+            Some(value) if !found.as_bytes().eq_ignore_ascii_case(value.as_bytes()) => {
+                Err(CsrfError::MissingHeader)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// How the [`RequiredHeader`] combines with the authenticity token, set with [`crate::CsrfLayer::with_required_header`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HeaderCheckMode {
+    /// Requests need both the header and a valid authenticity token.
+    #[default]
+    All,
+    /// Requests pass with either the header or a valid authenticity token, so scripts can skip the token.
+    Any,
+}
@@ -0,0 +1,657 @@
+use bytes::{Bytes, BytesMut};
+use http::HeaderMap;
+use http_body::{Body, Frame, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+/// Largest unfinished tag we will hold back between chunks before giving up on it.
+const MAX_PENDING: usize = 8 * 1024;
+
+pin_project! {
+    /// Response body that injects the hidden authenticity token input into post forms.
+    pub(crate) struct CsrfBody<B> {
+        #[pin]
+        inner: B,
+        rewriter: FormRewriter,
+        trailers: Option<HeaderMap>,
+        done: bool,
+    }
+}
+
+impl<B> CsrfBody<B> {
+    pub(crate) fn new(inner: B, rewriter: FormRewriter) -> Self {
+        Self {
+            inner,
+            rewriter,
+            trailers: None,
+            done: false,
+        }
+    }
+}
+
+impl<B> Body for CsrfBody<B>
+where
+    B: Body<Data = Bytes>,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+        let rewriter = this.rewriter;
+
+        loop {
+            if let Some(trailers) = this.trailers.take() {
+                return Poll::Ready(Some(Ok(Frame::trailers(trailers))));
+            }
+
+            if *this.done {
+                return Poll::Ready(None);
+            }
+
+            match ready!(this.inner.as_mut().poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => {
+                        let data = rewriter.push(&data);
+
+                        if !data.is_empty() {
+                            return Poll::Ready(Some(Ok(Frame::data(data))));
+                        }
+                    }
+                    Err(frame) => {
+                        *this.trailers = frame.into_trailers().ok();
+                        let data = rewriter.finish();
+
+                        if !data.is_empty() {
+                            return Poll::Ready(Some(Ok(Frame::data(data))));
+                        }
+                    }
+                },
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => {
+                    *this.done = true;
+                    let data = rewriter.finish();
+
+                    if !data.is_empty() {
+                        return Poll::Ready(Some(Ok(Frame::data(data))));
+                    }
+                }
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.done && self.trailers.is_none() && self.rewriter.pending.is_empty()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        // We do not know how many forms will get the input so the length is unknown.
+        SizeHint::default()
+    }
+}
+
+/// Elements whose content is not markup, along with how their content ends.
+const RAW_TEXT: &[(&[u8], &[u8])] = &[
+    (b"script", b"</script"),
+    (b"style", b"</style"),
+    (b"textarea", b"</textarea"),
+    (b"title", b"</title"),
+    (b"xmp", b"</xmp"),
+    (b"iframe", b"</iframe"),
+    (b"noembed", b"</noembed"),
+    (b"noframes", b"</noframes"),
+    (b"noscript", b"</noscript"),
+];
+
+/// Streaming scanner that finds `<form method="post">` tags and appends the hidden input.
+///
+/// Comments and the content of raw text elements such as `<script>` are passed through untouched.
+/// Only tags split across chunks are held back; everything else is sent as soon as it arrives.
+pub(crate) struct FormRewriter {
+    input: Bytes,
+    host: Option<String>,
+    pending: BytesMut,
+    /// How the comment or raw text element being passed through ends.
+    raw_text_end: Option<&'static [u8]>,
+}
+
+impl FormRewriter {
+    pub(crate) fn new(input: String, host: Option<String>) -> Self {
+        Self {
+            input: Bytes::from(input),
+            host,
+            pending: BytesMut::new(),
+            raw_text_end: None,
+        }
+    }
+
+    /// Rewrites the chunk returning the bytes that are safe to send.
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> Bytes {
+        self.pending.extend_from_slice(chunk);
+        let buf = self.pending.split().freeze();
+        let mut out = BytesMut::with_capacity(buf.len());
+        let mut pos = 0;
+
+        loop {
+            if let Some(end) = self.raw_text_end {
+                match find_ignore_case(&buf[pos..], end) {
+                    Some(offset) => {
+                        let stop = pos + offset + end.len();
+                        out.extend_from_slice(&buf[pos..stop]);
+                        pos = stop;
+                        self.raw_text_end = None;
+                    }
+                    None => {
+                        // Hold back what could be the start of the end split across chunks.
+                        let stop = buf.len() - (end.len() - 1).min(buf.len() - pos);
+                        out.extend_from_slice(&buf[pos..stop]);
+                        self.pending.extend_from_slice(&buf[stop..]);
+                        return out.freeze();
+                    }
+                }
+            }
+
+            let Some(offset) = buf[pos..].iter().position(|b| *b == b'<') else {
+                break;
+            };
+            let start = pos + offset;
+
+            match self.scan_tag(&buf[start..]) {
+                Tag::Incomplete if buf.len() - start <= MAX_PENDING => {
+                    out.extend_from_slice(&buf[pos..start]);
+                    self.pending.extend_from_slice(&buf[start..]);
+                    return out.freeze();
+                }
+                Tag::Form { len, inject: true } => {
+                    out.extend_from_slice(&buf[pos..start + len]);
+                    out.extend_from_slice(&self.input);
+                    pos = start + len;
+                }
+                Tag::Form { len, inject: false } => {
+                    out.extend_from_slice(&buf[pos..start + len]);
+                    pos = start + len;
+                }
+                Tag::RawText { len, end } => {
+                    out.extend_from_slice(&buf[pos..start + len]);
+                    pos = start + len;
+                    self.raw_text_end = Some(end);
+                }
+                Tag::Incomplete | Tag::Other => {
+                    out.extend_from_slice(&buf[pos..=start]);
+                    pos = start + 1;
+                }
+            }
+        }
+
+        out.extend_from_slice(&buf[pos..]);
+        out.freeze()
+    }
+
+    /// Returns whatever was held back waiting for the rest of a tag.
+    pub(crate) fn finish(&mut self) -> Bytes {
+        self.pending.split().freeze()
+    }
+
+    fn scan_tag(&self, buf: &[u8]) -> Tag {
+        const COMMENT: &[u8] = b"<!--";
+
+        if buf.len() < COMMENT.len() && COMMENT.starts_with(buf) {
+            return Tag::Incomplete;
+        }
+
+        if buf.starts_with(COMMENT) {
+            return Tag::RawText {
+                len: COMMENT.len(),
+                end: b"-->",
+            };
+        }
+
+        let Some(name_len) = buf[1..].iter().position(|b| !b.is_ascii_alphanumeric()) else {
+            return Tag::Incomplete;
+        };
+        let name_end = 1 + name_len;
+        let name = &buf[1..name_end];
+
+        if !matches!(
+            buf[name_end],
+            b'>' | b'/' | b' ' | b'\t' | b'\n' | b'\r' | b'\x0c'
+        ) {
+            return Tag::Other;
+        }
+
+        let raw_text_end = RAW_TEXT
+            .iter()
+            .find(|(raw_text, _)| raw_text.eq_ignore_ascii_case(name))
+            .map(|(_, end)| *end);
+
+        if raw_text_end.is_none() && !name.eq_ignore_ascii_case(b"form") {
+            return Tag::Other;
+        }
+
+        let Some(len) = tag_len(buf) else {
+            return Tag::Incomplete;
+        };
+
+        if let Some(end) = raw_text_end {
+            return Tag::RawText { len, end };
+        }
+
+        let mut method = None;
+        let mut action = None;
+
+        for (name, value) in Attributes::new(&buf[name_end..len - 1]) {
+            if name.eq_ignore_ascii_case(b"method") {
+                method.get_or_insert(value);
+            } else if name.eq_ignore_ascii_case(b"action") {
+                action.get_or_insert(value);
+            }
+        }
+
+        let inject = method.is_some_and(|method| method.trim_ascii().eq_ignore_ascii_case(b"post"))
+            && action.is_none_or(|action| is_same_origin(action, self.host.as_deref()));
+
+        Tag::Form { len, inject }
+    }
+}
+
+enum Tag {
+    /// More bytes are needed to know what this tag is.
+    Incomplete,
+    /// A complete form start tag of `len` bytes.
+    Form { len: usize, inject: bool },
+    /// A complete comment or raw text element start of `len` bytes, its content runs until `end`.
+    RawText { len: usize, end: &'static [u8] },
+    /// Any other tag.
+    Other,
+}
+
+/// Finds where `needle` first starts within `buf` ignoring ASCII case.
+fn find_ignore_case(buf: &[u8], needle: &[u8]) -> Option<usize> {
+    buf.windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle))
+}
+
+/// Finds the length of the tag up to and including the closing `>` ignoring any within quotes.
+fn tag_len(buf: &[u8]) -> Option<usize> {
+    let mut quote = None;
+
+    for (i, b) in buf.iter().enumerate() {
+        match (quote, *b) {
+            (None, b'"' | b'\'') => quote = Some(*b),
+            (None, b'>') => return Some(i + 1),
+            (Some(q), b) if q == b => quote = None,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Iterator over the name and value of each attribute within a tag.
+struct Attributes<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Attributes<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    fn take_while(&mut self, f: impl Fn(u8) -> bool) -> &'a [u8] {
+        let len = self
+            .buf
+            .iter()
+            .position(|b| !f(*b))
+            .unwrap_or(self.buf.len());
+        let (taken, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        taken
+    }
+}
+
+impl<'a> Iterator for Attributes<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.take_while(|b| b.is_ascii_whitespace() || b == b'/');
+
+            if self.buf.is_empty() {
+                return None;
+            }
+
+            let name = self.take_while(|b| !b.is_ascii_whitespace() && b != b'=' && b != b'/');
+            self.take_while(|b| b.is_ascii_whitespace());
+
+            if self.buf.first() != Some(&b'=') {
+                if name.is_empty() {
+                    continue;
+                }
+
+                return Some((name, &[]));
+            }
+
+            self.buf = &self.buf[1..];
+            self.take_while(|b| b.is_ascii_whitespace());
+
+            let value = match self.buf.first() {
+                Some(&quote) if quote == b'"' || quote == b'\'' => {
+                    self.buf = &self.buf[1..];
+                    let value = self.take_while(|b| b != quote);
+                    self.buf = self.buf.get(1..).unwrap_or_default();
+                    value
+                }
+                _ => self.take_while(|b| !b.is_ascii_whitespace()),
+            };
+
+            return Some((name, value));
+        }
+    }
+}
+
+/// Checks if the form's action posts back to the host the page was requested from.
+///
+/// The action is first read the way browsers do, so `&#47;&#47;evil.com` or `/\evil.com` are seen as `//evil.com`.
+fn is_same_origin(action: &[u8], host: Option<&str>) -> bool {
+    let action = normalize_action(action);
+    let action = action.as_slice();
+
+    let authority = if let Some(rest) = action.strip_prefix(b"//") {
+        rest
+    } else if let Some(colon) = scheme_len(action) {
+        let scheme = &action[..colon];
+
+        if !scheme.eq_ignore_ascii_case(b"http") && !scheme.eq_ignore_ascii_case(b"https") {
+            return false;
+        }
+
+        match action[colon + 1..].strip_prefix(b"//") {
+            Some(rest) => rest,
+            None => return false,
+        }
+    } else {
+        return true;
+    };
+
+    let authority = authority
+        .split(|b| matches!(b, b'/' | b'?' | b'#'))
+        .next()
+        .unwrap_or_default();
+    let authority = match authority.iter().rposition(|b| *b == b'@') {
+        Some(at) => &authority[at + 1..],
+        None => authority,
+    };
+
+    host.is_some_and(|host| host.as_bytes().eq_ignore_ascii_case(authority))
+}
+
+/// Decodes the character references that can hide a scheme or authority, drops the tabs and newlines
+/// url parsers ignore, trims leading and trailing spaces and control characters, and reads `\` as `/`.
+fn normalize_action(action: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(action.len());
+    let mut rest = action;
+
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'&' {
+            if let Some((c, len)) = char_reference(rest) {
+                let mut buf = [0u8; 4];
+                decoded.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                rest = &rest[len..];
+                continue;
+            }
+        }
+
+        decoded.push(b);
+        rest = tail;
+    }
+
+    decoded.retain(|b| !matches!(b, b'\t' | b'\n' | b'\r'));
+
+    let start = decoded
+        .iter()
+        .position(|b| *b > b' ')
+        .unwrap_or(decoded.len());
+    let end = decoded
+        .iter()
+        .rposition(|b| *b > b' ')
+        .map_or(start, |end| end + 1);
+    let mut action = decoded[start..end].to_vec();
+
+    for b in &mut action {
+        if *b == b'\\' {
+            *b = b'/';
+        }
+    }
+
+    action
+}
+
+/// Decodes the character reference at the start of `buf`, returning it and how many bytes it took.
+///
+/// Numeric references are decoded with or without their `;` like browsers do. Of the named ones only those that
+/// decode to a character changing how the url is read are needed, any other is left as is.
+fn char_reference(buf: &[u8]) -> Option<(char, usize)> {
+    const NAMED: &[(&[u8], char)] = &[
+        (b"&sol;", '/'),
+        (b"&bsol;", '\\'),
+        (b"&colon;", ':'),
+        (b"&Tab;", '\t'),
+        (b"&NewLine;", '\n'),
+        (b"&amp;", '&'),
+        (b"&AMP;", '&'),
+        (b"&amp", '&'),
+        (b"&AMP", '&'),
+    ];
+
+    let Some(numeric) = buf.strip_prefix(b"&#") else {
+        return NAMED
+            .iter()
+            .find(|(name, _)| buf.starts_with(name))
+            .map(|(name, c)| (*c, name.len()));
+    };
+
+    let (radix, digits) = match numeric.first() {
+        Some(b'x' | b'X') => (16, &numeric[1..]),
+        _ => (10, numeric),
+    };
+    let is_digit = |b: &u8| match radix {
+        16 => b.is_ascii_hexdigit(),
+        _ => b.is_ascii_digit(),
+    };
+    let len = digits
+        .iter()
+        .position(|b| !is_digit(b))
+        .unwrap_or(digits.len());
+
+    if len == 0 {
+        return None;
+    }
+
+    // Out of range values decode to the replacement character, which is harmless here.
+    let value = std::str::from_utf8(&digits[..len])
+        .ok()
+        .and_then(|digits| u32::from_str_radix(digits, radix).ok())
+        .and_then(char::from_u32)
+        .unwrap_or(char::REPLACEMENT_CHARACTER);
+    let semicolon = usize::from(digits.get(len) == Some(&b';'));
+
+    Some((value, buf.len() - digits.len() + len + semicolon))
+}
+
+/// Returns the position of the `:` ending the url scheme if the action has one.
+fn scheme_len(action: &[u8]) -> Option<usize> {
+    let colon = action.iter().position(|b| *b == b':')?;
+    let scheme = &action[..colon];
+
+    let valid = scheme.first().is_some_and(u8::is_ascii_alphabetic)
+        && scheme
+            .iter()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'-' | b'.'));
+
+    valid.then_some(colon)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = "<input/>";
+
+    fn rewrite(chunks: &[&str], host: Option<&str>) -> String {
+        let mut rewriter = FormRewriter::new(INPUT.to_owned(), host.map(str::to_owned));
+        let mut out = Vec::new();
+
+        for chunk in chunks {
+            out.extend_from_slice(&rewriter.push(chunk.as_bytes()));
+        }
+
+        out.extend_from_slice(&rewriter.finish());
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn injects_into_post_forms() {
+        assert_eq!(
+            rewrite(&[r#"<FORM Method="POST" action="/x">a</form>"#], None),
+            r#"<FORM Method="POST" action="/x"><input/>a</form>"#
+        );
+        assert_eq!(
+            rewrite(&["<form method=post>"], None),
+            "<form method=post><input/>"
+        );
+    }
+
+    #[test]
+    fn skips_other_forms_and_tags() {
+        let html = r#"<form method="get"><form><formx method="post"><p>"#;
+
+        assert_eq!(rewrite(&[html], None), html);
+    }
+
+    #[test]
+    fn handles_tags_split_across_chunks() {
+        assert_eq!(
+            rewrite(&["a<", "fo", r#"rm method="po"#, r#"st">b"#], None),
+            r#"a<form method="post"><input/>b"#
+        );
+    }
+
+    #[test]
+    fn ignores_gt_within_quotes() {
+        assert_eq!(
+            rewrite(&[r#"<form data-x="a>b" method="post">"#], None),
+            r#"<form data-x="a>b" method="post"><input/>"#
+        );
+    }
+
+    #[test]
+    fn only_injects_same_origin_actions() {
+        let host = Some("example.com:8080");
+
+        assert_eq!(
+            rewrite(
+                &[r#"<form method="post" action="https://example.com:8080/a">"#],
+                host
+            ),
+            r#"<form method="post" action="https://example.com:8080/a"><input/>"#
+        );
+        assert_eq!(
+            rewrite(
+                &[r#"<form method="post" action="//EXAMPLE.com:8080">"#],
+                host
+            ),
+            r#"<form method="post" action="//EXAMPLE.com:8080"><input/>"#
+        );
+
+        for action in [
+            "https://evil.com/",
+            "//evil.com",
+            "https://example.com/",
+            "javascript:alert(1)",
+        ] {
+            let html = format!(r#"<form method="post" action="{action}">"#);
+            assert_eq!(rewrite(&[&html], host), html);
+        }
+    }
+
+    #[test]
+    fn decodes_actions_like_browsers() {
+        let host = Some("example.com");
+
+        for action in [
+            r"/\evil.com",
+            r"\\evil.com",
+            "&#47;&#47;evil.com",
+            "&#x2F;&#X2f;evil.com",
+            "&#47&#47evil.com",
+            "&sol;&sol;evil.com",
+            "/&bsol;evil.com",
+            "/\t/evil.com",
+            "&#10;//evil.com",
+            "https&colon;//evil.com",
+        ] {
+            let html = format!(r#"<form method="post" action="{action}">"#);
+            assert_eq!(rewrite(&[&html], host), html, "{action}");
+        }
+
+        for action in ["/a?b=1&amp;c=2", "&#47;a", "?x&y", ""] {
+            let html = format!(r#"<form method="post" action="{action}">"#);
+            assert_eq!(
+                rewrite(&[&html], host),
+                format!("{html}{INPUT}"),
+                "{action}"
+            );
+        }
+    }
+
+    #[test]
+    fn absolute_actions_need_a_host() {
+        let html = r#"<form method="post" action="https://example.com/">"#;
+
+        assert_eq!(rewrite(&[html], None), html);
+    }
+
+    #[test]
+    fn skips_comments_and_raw_text() {
+        for html in [
+            r#"<script>var s = "<form method='post'>";</script>"#,
+            r#"<SCRIPT type="module">"<form method=post>"</SCRIPT>"#,
+            "<style>/* <form method=post> */</style>",
+            "<!-- <form method=post> -->",
+            "<textarea><form method=post></textarea>",
+        ] {
+            assert_eq!(rewrite(&[html], None), html);
+        }
+
+        assert_eq!(
+            rewrite(&["<!-- a --><form method=post>"], None),
+            "<!-- a --><form method=post><input/>"
+        );
+    }
+
+    #[test]
+    fn finds_raw_text_end_split_across_chunks() {
+        assert_eq!(
+            rewrite(
+                &[
+                    "<scr",
+                    "ipt>'<form method=post>'</scr",
+                    "ipt><form method=post>"
+                ],
+                None
+            ),
+            "<script>'<form method=post>'</script><form method=post><input/>"
+        );
+    }
+
+    #[test]
+    fn flushes_unfinished_tags() {
+        assert_eq!(rewrite(&["a<form method"], None), "a<form method");
+    }
+}
@@ -1,32 +1,239 @@
-use crate::{cookies::*, CsrfConfig, CsrfToken};
-use http::Request;
-use std::task::{Context, Poll};
+use crate::{
+    cookies::*, enforce::*, rewrite::*, CsrfEnforcement, CsrfLayer, CsrfToken, CsrfViolation,
+};
+use axum_core::{
+    body::Body,
+    response::{IntoResponse, Response},
+    BoxError,
+};
+use bytes::Bytes;
+use http::{
+    header::{CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, HOST, SET_COOKIE},
+    HeaderMap, Request, StatusCode,
+};
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
 use tower_service::Service;
 
 #[derive(Clone)]
 pub struct AxumCsrfService<S> {
-    pub(crate) config: CsrfConfig,
+    pub(crate) layer: CsrfLayer,
     pub(crate) inner: S,
 }
 
-impl<ResBody, S> Service<Request<ResBody>> for AxumCsrfService<S>
+impl<ResBody, S> Service<Request<Body>> for AxumCsrfService<S>
 where
-    S: Service<Request<ResBody>>,
+    S: Service<Request<Body>, Response = http::Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ResBody: http_body::Body<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<BoxError>,
 {
-    type Response = S::Response;
+    type Response = Response;
     type Error = S::Error;
-    type Future = S::Future;
+    type Future = ResponseFuture<S::Future, S::Error>;
 
     #[inline]
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         self.inner.poll_ready(cx)
     }
 
-    fn call(&mut self, mut req: Request<ResBody>) -> Self::Future {
-        let config = self.config.clone();
-        let token = get_token(&config, req.headers_mut());
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        let layer = self.layer.clone();
+        let load_token = || {
+            let (token, source) = get_token(&layer.config, req.headers());
+            CsrfToken::load(token, source, layer.config.clone(), Some(req.extensions()))
+        };
 
-        req.extensions_mut().insert(CsrfToken { token, config });
-        self.inner.call(req)
+        // The span is attached to the check's future below, as it runs after this returns.
+        #[cfg(feature = "tracing")]
+        let span = crate::trace::request_span(req.method(), req.uri(), req.headers());
+        #[cfg(feature = "tracing")]
+        let token = span.in_scope(load_token);
+        #[cfg(not(feature = "tracing"))]
+        let token = load_token();
+
+        // Nothing to check or rewrite, so call the inner service directly without boxing.
+        if layer.enforcement == CsrfEnforcement::Off && !layer.inject_forms {
+            req.extensions_mut().insert(token);
+
+            return ResponseFuture::Inner {
+                future: self.inner.call(req),
+            };
+        }
+
+        let injection = layer.inject_forms.then(|| {
+            let host = req
+                .headers()
+                .get(HOST)
+                .and_then(|host| host.to_str().ok())
+                .or_else(|| req.uri().authority().map(|authority| authority.as_str()))
+                .map(str::to_owned);
+
+            (token.clone(), host)
+        });
+
+        // The service that was polled ready is the one we must call, so leave the clone behind.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        let future = Box::pin(async move {
+            if layer.enforcement != CsrfEnforcement::Off {
+                let (mut parts, body) = req.into_parts();
+
+                // Forms rendered from a `SessionCsrfToken` are checked against the Session's Token.
+                #[cfg(feature = "tower-sessions")]
+                let session_token = CsrfToken::stored_in_session(&parts, &layer.config).await;
+                #[cfg(feature = "tower-sessions")]
+                let token = session_token.as_ref().unwrap_or(&token);
+                #[cfg(not(feature = "tower-sessions"))]
+                let token = &token;
+
+                let checked = check(&layer, token, &parts, body);
+                #[cfg(feature = "tracing")]
+                let checked = tracing::Instrument::instrument(checked, span.clone());
+
+                // The body could not be read back, so it must not reach the inner service.
+                let (outcome, body) = match checked.await {
+                    Ok(checked) => checked,
+                    Err(status) => return Ok(status.into_response()),
+                };
+
+                if let CsrfOutcome::Failed(err) = &outcome {
+                    let mut response = None;
+
+                    if let Some(handler) = &layer.violation_handler {
+                        let violation = CsrfViolation::new(
+                            &parts,
+                            err.clone(),
+                            token,
+                            layer.client_ip_header.as_ref(),
+                        );
+                        let on_violation = handler.on_violation(&violation);
+                        #[cfg(feature = "tracing")]
+                        let on_violation = tracing::Instrument::instrument(on_violation, span);
+                        response = on_violation.await;
+                    }
+
+                    if layer.enforcement == CsrfEnforcement::Enforce {
+                        let Some(mut response) = response else {
+                            return Ok((StatusCode::FORBIDDEN, "CSRF verification failed.")
+                                .into_response());
+                        };
+
+                        // The handler may render the Token, so its cookie must reach the browser too.
+                        let name = token.config.prefixed_cookie_name();
+                        if !has_cookie(response.headers(), &name) {
+                            token.set_cookie(response.headers_mut());
+                        }
+
+                        return Ok(response);
+                    }
+                }
+
+                parts.extensions.insert(outcome);
+                req = Request::from_parts(parts, body);
+            }
+
+            req.extensions_mut().insert(token);
+
+            let res = inner.call(req).await?;
+            Ok(inject_forms(res, injection))
+        });
+
+        ResponseFuture::Boxed { future }
     }
 }
+
+pin_project! {
+    /// Response future of [`AxumCsrfService`], only boxed when the request is checked or the response rewritten.
+    #[project = ResponseFutureProj]
+    pub enum ResponseFuture<F, E> {
+        Inner {
+            #[pin]
+            future: F,
+        },
+        Boxed {
+            future: Pin<Box<dyn Future<Output = Result<Response, E>> + Send>>,
+        },
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F, E>
+where
+    F: Future<Output = Result<http::Response<B>, E>>,
+    B: http_body::Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    type Output = Result<Response, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            ResponseFutureProj::Inner { future } => {
+                Poll::Ready(ready!(future.poll(cx)).map(|res| res.map(Body::new)))
+            }
+            ResponseFutureProj::Boxed { future } => future.as_mut().poll(cx),
+        }
+    }
+}
+
+/// Wraps the response body so the hidden input gets injected into html forms when enabled.
+fn inject_forms<B>(
+    res: http::Response<B>,
+    injection: Option<(CsrfToken, Option<String>)>,
+) -> Response
+where
+    B: http_body::Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    let Some((token, host)) = injection else {
+        return res.map(Body::new);
+    };
+
+    if !is_rewritable(res.headers()) {
+        return res.map(Body::new);
+    }
+
+    let input = token.hidden_input();
+
+    let (mut parts, body) = res.into_parts();
+
+    // The injected inputs change the length so we let it get sent chunked instead.
+    parts.headers.remove(CONTENT_LENGTH);
+
+    // The injected token is useless unless its cookie also reaches the browser.
+    if !has_cookie(&parts.headers, &token.config.prefixed_cookie_name()) {
+        token.set_cookie(&mut parts.headers);
+    }
+
+    let rewriter = FormRewriter::new(input.to_string(), host);
+    Response::from_parts(parts, Body::new(CsrfBody::new(body, rewriter)))
+}
+
+/// Only uncompressed html responses can be rewritten.
+fn is_rewritable(headers: &HeaderMap) -> bool {
+    let is_html = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("text/html"));
+
+    let is_encoded = headers
+        .get_all(CONTENT_ENCODING)
+        .iter()
+        .any(|value| value.as_bytes() != b"identity");
+
+    is_html && !is_encoded
+}
+
+fn has_cookie(headers: &HeaderMap, name: &str) -> bool {
+    headers.get_all(SET_COOKIE).iter().any(|value| {
+        value
+            .as_bytes()
+            .strip_prefix(name.as_bytes())
+            .is_some_and(|rest| rest.first() == Some(&b'='))
+    })
+}
@@ -0,0 +1,256 @@
+use crate::{token::TokenSource, CsrfConfig, CsrfError, CsrfToken};
+#[cfg(not(feature = "layer"))]
+use axum_core::extract::FromRef;
+use axum_core::extract::FromRequestParts;
+use http::{self, request::Parts};
+use tower_sessions::Session;
+
+impl CsrfToken {
+    /// Loads the Token from the Session or creates and stores a new one if none exists.
+    ///
+    /// The Token is stored under the configured cookie name and follows the Session's
+    /// lifecycle, so no CSRF cookie gets set when it is returned within a response.
+    pub async fn from_session(session: &Session, config: CsrfConfig) -> Result<Self, CsrfError> {
+        let stored = session
+            .get::<String>(&config.inner.cookie_name)
+            .await
+            .map_err(|_| CsrfError::Session)?;
+        let is_new = stored.is_none();
+        let source = if is_new {
+            TokenSource::Fresh
+        } else {
+            TokenSource::Session
+        };
+
+        let mut token = CsrfToken::load(stored, source, config, None);
+        token.in_session = true;
+
+        if is_new {
+            session
+                .insert(&token.config.inner.cookie_name, &token.token)
+                .await
+                .map_err(|_| CsrfError::Session)?;
+        }
+
+        Ok(token)
+    }
+
+    /// Replaces the Token stored within the Session with a new one.
+    ///
+    /// Call this when a user logs in or out so a Token issued before can no longer be used.
+    pub async fn renew_session(session: &Session, config: CsrfConfig) -> Result<Self, CsrfError> {
+        let mut token = CsrfToken::load(None, TokenSource::Fresh, config, None);
+        token.in_session = true;
+
+        session
+            .insert(&token.config.inner.cookie_name, &token.token)
+            .await
+            .map_err(|_| CsrfError::Session)?;
+
+        Ok(token)
+    }
+
+    /// Loads the Token stored within the request's Session, if it has one, for the layer to check against.
+    #[cfg(feature = "layer")]
+    pub(crate) async fn stored_in_session(parts: &Parts, config: &CsrfConfig) -> Option<Self> {
+        let session = parts.extensions.get::<Session>()?;
+        let stored = session
+            .get::<String>(&config.inner.cookie_name)
+            .await
+            .ok()??;
+
+        let mut token = CsrfToken::load(Some(stored), TokenSource::Session, config.clone(), None);
+        token.in_session = true;
+
+        #[cfg(feature = "metrics")]
+        {
+            token.route = crate::stats::route(&parts.extensions);
+        }
+
+        Some(token)
+    }
+}
+
+/// Extracts a [`CsrfToken`] that is stored within the `tower-sessions` [`Session`] instead of a cookie.
+///
+/// Requires the `SessionManagerLayer` to be added to the router.
+#[derive(Clone)]
+pub struct SessionCsrfToken(pub CsrfToken);
+
+#[cfg(not(feature = "layer"))]
+impl<S> FromRequestParts<S> for SessionCsrfToken
+where
+    S: Send + Sync,
+    CsrfConfig: FromRef<S>,
+{
+    type Rejection = (http::StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let config = CsrfConfig::from_ref(state);
+        session_token(parts, config).await
+    }
+}
+
+#[cfg(feature = "layer")]
+impl<S> FromRequestParts<S> for SessionCsrfToken
+where
+    S: Send + Sync,
+{
+    type Rejection = (http::StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let config = parts
+            .extensions
+            .get::<CsrfToken>()
+            .map(|token| token.config.clone())
+            .ok_or((
+                http::StatusCode::INTERNAL_SERVER_ERROR,
+                "Can't extract CsrfConfig. Is `CSRFLayer` enabled?",
+            ))?;
+
+        session_token(parts, config).await
+    }
+}
+
+async fn session_token(
+    parts: &Parts,
+    config: CsrfConfig,
+) -> Result<SessionCsrfToken, (http::StatusCode, &'static str)> {
+    let session = parts.extensions.get::<Session>().cloned().ok_or((
+        http::StatusCode::INTERNAL_SERVER_ERROR,
+        "Can't extract Session. Is `SessionManagerLayer` enabled?",
+    ))?;
+
+    CsrfToken::from_session(&session, config)
+        .await
+        .map(SessionCsrfToken)
+        .map_err(|_| {
+            (
+                http::StatusCode::INTERNAL_SERVER_ERROR,
+                "Could not load the CSRF Token from the Session.",
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::{Request, StatusCode};
+    use std::sync::Arc;
+    use tower_sessions::MemoryStore;
+
+    fn session() -> Session {
+        Session::new(None, Arc::new(MemoryStore::default()), None)
+    }
+
+    /// Builds the request parts the extractor sees, with the layer's Token when it is enabled.
+    fn parts(session: Option<Session>, config: &CsrfConfig) -> Parts {
+        let (mut parts, _) = Request::new(()).into_parts();
+        if let Some(session) = session {
+            parts.extensions.insert(session);
+        }
+
+        #[cfg(feature = "layer")]
+        parts.extensions.insert(CsrfToken::load(
+            None,
+            TokenSource::Fresh,
+            config.clone(),
+            None,
+        ));
+        #[cfg(not(feature = "layer"))]
+        let _ = config;
+
+        parts
+    }
+
+    #[tokio::test]
+    async fn session_token_is_created_on_first_use() {
+        let session = session();
+        let config = CsrfConfig::default();
+        let token = CsrfToken::from_session(&session, config.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(token.source(), TokenSource::Fresh);
+        assert_eq!(
+            session
+                .get::<String>(&config.inner.cookie_name)
+                .await
+                .unwrap(),
+            Some(token.token.clone())
+        );
+        assert!(token.verify(&token.authenticity_token()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn session_token_is_reused_within_the_session() {
+        let session = session();
+        let config = CsrfConfig::default();
+        let first = CsrfToken::from_session(&session, config.clone())
+            .await
+            .unwrap();
+        let second = CsrfToken::from_session(&session, config).await.unwrap();
+
+        assert_eq!(second.source(), TokenSource::Session);
+        assert_eq!(second.token, first.token);
+        assert!(second.verify(&first.authenticity_token()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn renew_session_rotates_the_token() {
+        let session = session();
+        let config = CsrfConfig::default();
+        let before = CsrfToken::from_session(&session, config.clone())
+            .await
+            .unwrap();
+        let renewed = CsrfToken::renew_session(&session, config.clone())
+            .await
+            .unwrap();
+        let after = CsrfToken::from_session(&session, config).await.unwrap();
+
+        assert_ne!(renewed.token, before.token);
+        assert_eq!(after.token, renewed.token);
+        assert!(after.verify(&before.authenticity_token()).is_err());
+    }
+
+    #[tokio::test]
+    async fn extractor_loads_the_session_token() {
+        let session = session();
+        let config = CsrfConfig::default();
+        let mut parts = parts(Some(session.clone()), &config);
+
+        let SessionCsrfToken(first) = SessionCsrfToken::from_request_parts(&mut parts, &config)
+            .await
+            .unwrap();
+        let SessionCsrfToken(second) = SessionCsrfToken::from_request_parts(&mut parts, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(second.token, first.token);
+        assert_eq!(
+            session
+                .get::<String>(&config.inner.cookie_name)
+                .await
+                .unwrap(),
+            Some(first.token)
+        );
+    }
+
+    #[tokio::test]
+    async fn extractor_rejects_without_session() {
+        let config = CsrfConfig::default();
+        let mut parts = parts(None, &config);
+
+        let Err((status, message)) =
+            SessionCsrfToken::from_request_parts(&mut parts, &config).await
+        else {
+            panic!("extracted a Token without a Session");
+        };
+
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            message,
+            "Can't extract Session. Is `SessionManagerLayer` enabled?"
+        );
+    }
+}
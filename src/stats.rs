@@ -0,0 +1,54 @@
+use crate::CsrfToken;
+use axum::extract::MatchedPath;
+use http::Extensions;
+use time::OffsetDateTime;
+
+/// Label used when the route pattern is unknown so raw paths never become labels.
+const UNKNOWN_ROUTE: &str = "unknown";
+
+pub(crate) fn route(extensions: &Extensions) -> Option<MatchedPath> {
+    extensions.get::<MatchedPath>().cloned()
+}
+
+fn route_label(route: Option<&MatchedPath>) -> String {
+    route.map_or(UNKNOWN_ROUTE, MatchedPath::as_str).to_owned()
+}
+
+pub(crate) fn token_issued(route: Option<&MatchedPath>) {
+    ::metrics::counter!("axum_csrf_tokens_issued_total", "route" => route_label(route))
+        .increment(1);
+}
+
+pub(crate) fn cookie_reused(route: Option<&MatchedPath>) {
+    ::metrics::counter!("axum_csrf_cookies_reused_total", "route" => route_label(route))
+        .increment(1);
+}
+
+pub(crate) fn verify_passed(token: &CsrfToken) {
+    ::metrics::counter!(
+        "axum_csrf_verifications_passed_total",
+        "route" => route_label(token.route.as_ref())
+    )
+    .increment(1);
+}
+
+pub(crate) fn verify_failed(token: &CsrfToken, reason: &'static str) {
+    ::metrics::counter!(
+        "axum_csrf_verifications_failed_total",
+        "route" => route_label(token.route.as_ref()),
+        "reason" => reason
+    )
+    .increment(1);
+}
+
+pub(crate) fn token_age(token: &CsrfToken) {
+    if let Some(issued_at) = token.issued_at() {
+        let age = OffsetDateTime::now_utc() - issued_at;
+
+        ::metrics::histogram!(
+            "axum_csrf_token_age_seconds",
+            "route" => route_label(token.route.as_ref())
+        )
+        .record(age.as_seconds_f64());
+    }
+}
@@ -0,0 +1,183 @@
+//! Helpers for testing handlers behind CSRF protection.
+//!
+//! These build the encrypted or signed cookie and its matching authenticity token the same
+//! way a browser would have received them, so requests can be sent with `tower::ServiceExt::oneshot`.
+
+use crate::{
+    cookies::{decode_cookie, get_token, CookiesExt},
+    token::TokenSource,
+    CsrfConfig, CsrfToken,
+};
+use cookie::{Cookie, CookieJar};
+use http::{
+    header::{COOKIE, SET_COOKIE},
+    HeaderName, HeaderValue, Request, Response,
+};
+
+/// A CSRF cookie and its matching authenticity token.
+///
+/// # Examples
+/// ```rust
+/// use axum_csrf::{test_util::TestToken, CsrfConfig};
+/// use http::Request;
+///
+/// let config = CsrfConfig::default();
+/// let test_token = TestToken::new(config);
+///
+/// let mut request = Request::post("/").body(()).unwrap();
+/// test_token.apply(&mut request);
+///
+/// assert!(request.headers().contains_key("cookie"));
+/// assert!(test_token.token().verify(test_token.authenticity_token()).is_ok());
+/// ```
+#[derive(Clone)]
+pub struct TestToken {
+    token: CsrfToken,
+    cookie: HeaderValue,
+    authenticity_token: String,
+}
+
+impl TestToken {
+    /// Issues a new Token using the [`CsrfConfig`]'s cookie name, mode and salt.
+    ///
+    /// The Token is sourced as if its cookie was sent, so a wrong authenticity token fails with
+    /// [`crate::CsrfError::Verify`] like it would within a handler.
+    pub fn new(config: CsrfConfig) -> Self {
+        let mut token = CsrfToken::load(None, TokenSource::Fresh, config, None);
+        token.source = TokenSource::Cookie;
+
+        let mut jar = CookieJar::new();
+        jar.add_cookie(token.build_cookie(), &token.config.inner.cookie_mode);
+
+        let name = token.config.prefixed_cookie_name();
+        let cookie = jar
+            .get(&name)
+            .map(|cookie| cookie.stripped().encoded().to_string())
+            .and_then(|cookie| HeaderValue::try_from(cookie).ok())
+            .expect("CSRF cookie is a valid header value");
+
+        let authenticity_token = token.authenticity_token();
+
+        Self {
+            token,
+            cookie,
+            authenticity_token,
+        }
+    }
+
+    /// The Token as a handler would extract it.
+    pub fn token(&self) -> &CsrfToken {
+        &self.token
+    }
+
+    /// Value for the request's `Cookie` header.
+    pub fn cookie_header(&self) -> &HeaderValue {
+        &self.cookie
+    }
+
+    /// The authenticity token to send within a form field or header.
+    pub fn authenticity_token(&self) -> &str {
+        &self.authenticity_token
+    }
+
+    /// Adds the `Cookie` header and the default `x-csrf-token` header to the request.
+    pub fn apply<B>(&self, request: &mut Request<B>) {
+        self.apply_with_header(request, HeaderName::from_static("x-csrf-token"));
+    }
+
+    /// Adds the `Cookie` header and the authenticity token within the given header to the request.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the authenticity token is not a valid header value.
+    pub fn apply_with_header<B>(&self, request: &mut Request<B>, header: HeaderName) {
+        let headers = request.headers_mut();
+        headers.append(COOKIE, self.cookie.clone());
+        headers.insert(
+            header,
+            HeaderValue::try_from(&self.authenticity_token)
+                .expect("authenticity token is a valid header value"),
+        );
+    }
+}
+
+/// Loads the [`CsrfToken`] from a request's `Cookie` headers the same way the extractor does.
+///
+/// A new Token is issued if the cookie is missing or could not be decrypted or verified.
+///
+/// # Examples
+/// ```rust
+/// use axum_csrf::{test_util::{token_from_request, TestToken}, CsrfConfig};
+/// use http::Request;
+///
+/// let config = CsrfConfig::default();
+/// let test_token = TestToken::new(config.clone());
+///
+/// let mut request = Request::post("/").body(()).unwrap();
+/// test_token.apply(&mut request);
+///
+/// let token = token_from_request(&request, config);
+/// assert!(token.verify(test_token.authenticity_token()).is_ok());
+/// ```
+pub fn token_from_request<B>(request: &Request<B>, config: CsrfConfig) -> CsrfToken {
+    let (token, source) = get_token(&config, request.headers());
+    CsrfToken::load(token, source, config, None)
+}
+
+/// Reads the CSRF cookie from a response's `Set-Cookie` headers back into a [`CsrfToken`].
+///
+/// Returns `None` if the response did not set the cookie or it could not be decrypted or verified.
+///
+/// # Examples
+/// ```rust
+/// use axum_csrf::{test_util::{token_from_response, TestToken}, CsrfConfig};
+/// use axum_core::response::IntoResponse;
+///
+/// let config = CsrfConfig::default();
+/// let response = TestToken::new(config.clone()).token().clone().into_response();
+///
+/// let token = token_from_response(&response, config).unwrap();
+/// assert!(token.verify(&token.authenticity_token()).is_ok());
+/// ```
+pub fn token_from_response<B>(response: &Response<B>, config: CsrfConfig) -> Option<CsrfToken> {
+    let name = config.prefixed_cookie_name();
+
+    let value = response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .filter_map(|value| Cookie::parse_encoded(value).ok())
+        .find(|cookie| cookie.name() == name)
+        .and_then(|cookie| decode_cookie(&name, cookie.value(), &config.inner.cookie_mode))?;
+
+    Some(CsrfToken::load(
+        Some(value),
+        TokenSource::Cookie,
+        config,
+        None,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CsrfError;
+
+    #[test]
+    fn test_token_is_sourced_from_its_cookie() {
+        let config = CsrfConfig::default();
+        let test_token = TestToken::new(config.clone());
+        let other = TestToken::new(config);
+
+        assert_eq!(test_token.token().source(), TokenSource::Cookie);
+        assert!(test_token
+            .token()
+            .verify(test_token.authenticity_token())
+            .is_ok());
+        assert_eq!(
+            test_token.token().verify(other.authenticity_token()),
+            Err(CsrfError::Verify)
+        );
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{cookies::*, CsrfConfig, CsrfError};
+use crate::{
+    cookies::*, encoding::DecodeError, mac::MAX_MAC_LEN, CsrfConfig, CsrfError, CsrfHiddenInput,
+    CsrfMetaTag,
+};
 #[cfg(not(feature = "layer"))]
 use axum_core::extract::FromRef;
 use axum_core::{
@@ -6,12 +9,44 @@ use axum_core::{
     response::{IntoResponse, IntoResponseParts, Response, ResponseParts},
 };
 use cookie::{Cookie, CookieJar, Expiration};
-use http::{self, request::Parts};
+use http::{self, request::Parts, HeaderMap};
 use std::convert::Infallible;
 
-use base64ct::{Base64, Encoding};
-use hmac::{Hmac, Mac};
-use sha2::Sha256;
+/// Where the [`CsrfToken`]'s value came from.
+///
+/// When a new Token had to be issued the authenticity token sent with the request can not match it,
+/// so handlers can tell a stale or tampered cookie apart from a forged request, for example to
+/// render the form again with the new Token instead of answering with a `403 Forbidden`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TokenSource {
+    /// No Token was stored so a new one was issued.
+    Fresh,
+    /// Loaded from the CSRF cookie.
+    Cookie,
+    /// The CSRF cookie could not be decrypted or verified so a new one was issued.
+    CookieInvalid,
+    /// More than one CSRF cookie was sent and rejected so a new one was issued.
+    CookieDuplicate,
+    /// The CSRF cookie's Token was older than the configured lifespan so a new one was issued.
+    CookieExpired,
+    /// Loaded from the Session.
+    #[cfg(feature = "tower-sessions")]
+    Session,
+}
+
+impl TokenSource {
+    /// Returns true if a new Token was issued for this request instead of loading a stored one.
+    pub fn is_new(&self) -> bool {
+        matches!(
+            self,
+            TokenSource::Fresh
+                | TokenSource::CookieInvalid
+                | TokenSource::CookieDuplicate
+                | TokenSource::CookieExpired
+        )
+    }
+}
 
 /// This is the Token that is generated when a user is routed to a page.
 /// If a Cookie exists then it will be used as the Token.
@@ -20,6 +55,12 @@ use sha2::Sha256;
 pub struct CsrfToken {
     pub(crate) token: String,
     pub(crate) config: CsrfConfig,
+    /// Set when the Token is stored within the Session so no cookie gets sent.
+    pub(crate) in_session: bool,
+    pub(crate) source: TokenSource,
+    /// Route the Token was loaded for, used to label metrics.
+    #[cfg(feature = "metrics")]
+    pub(crate) route: Option<axum::extract::MatchedPath>,
 }
 
 /// this auto pulls a Cookies nd Generates the CsrfToken from the extensions
@@ -33,9 +74,18 @@ where
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
         let config = CsrfConfig::from_ref(state);
-        let token = get_token(&config, &mut parts.headers);
 
-        Ok(CsrfToken { token, config })
+        #[cfg(feature = "tracing")]
+        let _span = crate::trace::request_span(&parts.method, &parts.uri, &parts.headers).entered();
+
+        let (token, source) = get_token(&config, &parts.headers);
+
+        Ok(CsrfToken::load(
+            token,
+            source,
+            config,
+            Some(&parts.extensions),
+        ))
     }
 }
 
@@ -60,35 +110,7 @@ impl IntoResponseParts for CsrfToken {
     type Error = Infallible;
 
     fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
-        let mut jar = CookieJar::new();
-        let lifespan = time::OffsetDateTime::now_utc() + self.config.lifespan;
-
-        let mut cookie_builder = Cookie::build((
-            if self.config.prefix_with_host {
-                let mut prefixed = "__Host-".to_owned();
-                prefixed.push_str(&self.config.cookie_name);
-                prefixed
-            } else {
-                self.config.cookie_name.clone()
-            },
-            self.token.clone(),
-        ))
-        .path(self.config.cookie_path.clone())
-        .secure(self.config.cookie_secure)
-        .http_only(self.config.cookie_http_only)
-        .same_site(self.config.cookie_same_site);
-
-        if self.config.lifespan > time::Duration::seconds(0) {
-            cookie_builder = cookie_builder.expires(Expiration::DateTime(lifespan));
-        }
-
-        if let Some(domain) = &self.config.cookie_domain {
-            cookie_builder = cookie_builder.domain(domain.clone());
-        }
-
-        jar.add_cookie(cookie_builder.build(), &self.config.key);
-
-        set_cookies(jar, res.headers_mut());
+        self.set_cookie(res.headers_mut());
         Ok(res)
     }
 }
@@ -100,27 +122,600 @@ impl IntoResponse for CsrfToken {
 }
 
 impl CsrfToken {
+    /// Creates the Token from the value stored in the cookie or Session, issuing a new one if there is none.
+    ///
+    /// `source` is where the value came from, or why there is none.
+    pub(crate) fn load(
+        token: Option<String>,
+        source: TokenSource,
+        config: CsrfConfig,
+        extensions: Option<&http::Extensions>,
+    ) -> Self {
+        #[cfg(feature = "metrics")]
+        let route = extensions.and_then(crate::stats::route);
+        #[cfg(not(feature = "metrics"))]
+        let _ = extensions;
+
+        // An expired cookie would fail every check until it is cleared, so replace it.
+        let (token, source) = match token {
+            Some(token) if source == TokenSource::Cookie && is_expired(&token, &config) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("CSRF cookie has expired, a new token will be issued");
+
+                (None, TokenSource::CookieExpired)
+            }
+            token => (token, source),
+        };
+
+        let token = match token {
+            Some(token) => {
+                #[cfg(feature = "metrics")]
+                crate::stats::cookie_reused(route.as_ref());
+
+                token
+            }
+            None => {
+                #[cfg(feature = "metrics")]
+                crate::stats::token_issued(route.as_ref());
+
+                generate_token(&config)
+            }
+        };
+
+        CsrfToken {
+            token,
+            config,
+            in_session: false,
+            source,
+            #[cfg(feature = "metrics")]
+            route,
+        }
+    }
+
+    /// Returns where the Token's value came from.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use axum_csrf::{CsrfToken, TokenSource};
+    ///
+    /// fn cookie_was_rejected(token: &CsrfToken) -> bool {
+    ///     token.source() == TokenSource::CookieInvalid
+    /// }
+    /// ```
+    pub fn source(&self) -> TokenSource {
+        self.source
+    }
+
+    /// Returns when the Token was issued.
+    ///
+    /// Tokens issued by older versions do not contain the time and return `None`.
+    pub fn issued_at(&self) -> Option<time::OffsetDateTime> {
+        issued_at(&self.token)
+    }
+
+    /// Builds the cookie containing the Token using the configured cookie settings.
+    pub(crate) fn build_cookie(&self) -> Cookie<'static> {
+        let lifespan = time::OffsetDateTime::now_utc() + self.config.inner.lifespan;
+
+        let mut cookie_builder =
+            Cookie::build((self.config.prefixed_cookie_name(), self.token.clone()))
+                .path(self.config.inner.cookie_path.clone())
+                .secure(self.config.inner.cookie_secure)
+                .http_only(self.config.inner.cookie_http_only)
+                .same_site(self.config.inner.cookie_same_site);
+
+        if self.config.inner.lifespan > time::Duration::seconds(0) {
+            cookie_builder = cookie_builder.expires(Expiration::DateTime(lifespan));
+        }
+
+        if let Some(domain) = &self.config.inner.cookie_domain {
+            cookie_builder = cookie_builder.domain(domain.clone());
+        }
+
+        cookie_builder.build()
+    }
+
+    /// Appends the Set-Cookie header containing the Token to the headers.
+    pub(crate) fn set_cookie(&self, headers: &mut HeaderMap) {
+        if self.in_session {
+            return;
+        }
+
+        let mut jar = CookieJar::new();
+        jar.add_cookie(self.build_cookie(), &self.config.inner.cookie_mode);
+
+        set_cookies(jar, headers);
+    }
+
     ///Used to get the hashed Token to place within the form.
-    pub fn authenticity_token(&self) -> Result<String, crate::CsrfError> {
-        let mut mac = Hmac::<Sha256>::new_from_slice(self.config.salt.as_bytes())
-            .map_err(|_| CsrfError::Salt)?;
-        mac.update(self.token.as_bytes());
+    pub fn authenticity_token(&self) -> String {
+        let mac = &self.config.inner.mac;
+        let mut buf = [0u8; MAX_MAC_LEN];
+        let tag = &mut buf[..mac.output_len()];
+        mac.sign(self.token.as_bytes(), tag);
 
-        let result = mac.finalize();
-        let bytes = result.into_bytes();
-        Ok(Base64::encode_string(&bytes))
+        let mut authenticity_token = String::from(mac.id());
+        authenticity_token.push('.');
+        self.config
+            .inner
+            .encoding
+            .encode(tag, &mut authenticity_token);
+        authenticity_token
     }
 
     ///Verifies that the form returned Token and the cookie tokens match.
     pub fn verify(&self, form_authenticity_token: &str) -> Result<(), crate::CsrfError> {
-        let mut mac = Hmac::<Sha256>::new_from_slice(self.config.salt.as_bytes())
-            .map_err(|_| CsrfError::Salt)?;
-        mac.update(self.token.as_bytes());
+        #[cfg(feature = "metrics")]
+        crate::stats::token_age(self);
+
+        let result = self.check(form_authenticity_token);
+
+        #[cfg(feature = "tracing")]
+        match &result {
+            Ok(()) => tracing::trace!("CSRF verification passed"),
+            Err(err) => tracing::debug!(reason = err.code(), "CSRF verification failed"),
+        }
+
+        #[cfg(feature = "metrics")]
+        match &result {
+            Ok(()) => crate::stats::verify_passed(self),
+            Err(err) => crate::stats::verify_failed(self, err.code()),
+        }
+
+        result
+    }
+
+    /// Verifies the authenticity token without recording it, for the layer which records its own outcome.
+    pub(crate) fn check(&self, form_authenticity_token: &str) -> Result<(), crate::CsrfError> {
+        let mac = &self.config.inner.mac;
+
+        // Tokens rendered before the algorithm identifier was added have none and use the configured MAC.
+        let encoded = match form_authenticity_token.split_once('.') {
+            Some((id, encoded)) if id == mac.id() => encoded,
+            Some(_) => return Err(CsrfError::Algorithm),
+            None => form_authenticity_token,
+        };
+
+        let encoding = self.config.inner.encoding;
+        let expected = mac.output_len();
+        let mut buf = [0u8; MAX_MAC_LEN];
+
+        let decoded = if self.config.inner.tolerant_decoding {
+            encoding.decode_tolerant(encoded, &mut buf, expected)
+        } else {
+            encoding.decode(encoded, &mut buf)
+        };
+
+        let form_token = match decoded {
+            Ok(len) if len == expected => &buf[..len],
+            Ok(found) | Err(DecodeError::TooLong(found)) => {
+                return Err(CsrfError::TokenLength { expected, found })
+            }
+            Err(DecodeError::Invalid) => return Err(CsrfError::MalformedToken),
+        };
+
+        // A new Token was issued for this request so it can never match, tell why instead.
+        if !mac.verify(self.token.as_bytes(), form_token) {
+            return Err(match self.source {
+                TokenSource::Fresh if !self.in_session => CsrfError::CookieMissing,
+                TokenSource::CookieInvalid => CsrfError::CookieInvalid,
+                TokenSource::CookieDuplicate => CsrfError::DuplicateCookie,
+                TokenSource::CookieExpired => CsrfError::Expired,
+                _ => CsrfError::Verify,
+            });
+        }
+
+        if self.is_expired() {
+            return Err(CsrfError::Expired);
+        }
 
-        mac.verify_slice(
-            &Base64::decode_vec(form_authenticity_token).map_err(|_| CsrfError::PasswordHash)?,
-        )
-        .map_err(|_| CsrfError::Verify)?;
         Ok(())
     }
+
+    /// Checks if the Token is older than the configured lifespan.
+    fn is_expired(&self) -> bool {
+        !self.in_session && is_expired(&self.token, &self.config)
+    }
+
+    ///Returns the url with the hashed Token appended to its query string.
+    ///
+    ///The query parameter is named after the configured field name and is percent encoded, any fragment is kept last.
+    ///Tokens within urls can leak through logs and the Referer header, so prefer forms or headers when you can.
+    pub fn url_with_token(&self, url: &str) -> String {
+        let authenticity_token = self.authenticity_token();
+        let (url, fragment) = match url.find('#') {
+            Some(index) => url.split_at(index),
+            None => (url, ""),
+        };
+
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        query.append_pair(&self.config.inner.field_name, &authenticity_token);
+
+        let separator = match url.find('?') {
+            None => "?",
+            Some(index) if index + 1 == url.len() || url.ends_with('&') => "",
+            Some(_) => "&",
+        };
+
+        format!("{url}{separator}{}{fragment}", query.finish())
+    }
+
+    ///Verifies the hashed Token sent within the uri's query string, see [`CsrfToken::url_with_token`].
+    pub fn verify_query(&self, uri: &http::Uri) -> Result<(), crate::CsrfError> {
+        match self.query_token(uri) {
+            Some(query_token) => self.verify(&query_token),
+            None => Err(CsrfError::MissingToken),
+        }
+    }
+
+    /// Reads the hashed Token from the query parameter named after the configured field name.
+    pub(crate) fn query_token(&self, uri: &http::Uri) -> Option<String> {
+        form_urlencoded::parse(uri.query()?.as_bytes())
+            .find(|(key, _)| *key == *self.config.inner.field_name)
+            .map(|(_, value)| value.into_owned())
+    }
+
+    ///Used to get a hidden form input containing the hashed Token.
+    ///
+    ///The input is named after the configured field name and is HTML escaped.
+    pub fn hidden_input(&self) -> CsrfHiddenInput {
+        CsrfHiddenInput {
+            field_name: self.config.inner.field_name.to_string(),
+            authenticity_token: self.authenticity_token(),
+        }
+    }
+
+    ///Used to get the `csrf-param` and `csrf-token` meta tags containing the hashed Token.
+    ///
+    ///Useful for javascript that needs to read the Token to send it within a header.
+    pub fn meta_tag(&self) -> CsrfMetaTag {
+        CsrfMetaTag {
+            field_name: self.config.inner.field_name.to_string(),
+            authenticity_token: self.authenticity_token(),
+        }
+    }
+}
+
+/// Reads the unix time a Token was issued at from its end.
+fn issued_at(token: &str) -> Option<time::OffsetDateTime> {
+    let (_, issued_at) = token.rsplit_once('.')?;
+    time::OffsetDateTime::from_unix_timestamp(issued_at.parse().ok()?).ok()
+}
+
+/// Checks if the Token is older than the configured lifespan, Tokens without an issue time never expire.
+fn is_expired(token: &str, config: &CsrfConfig) -> bool {
+    if config.inner.lifespan <= time::Duration::seconds(0) {
+        return false;
+    }
+
+    issued_at(token).is_some_and(|issued_at| {
+        issued_at + config.inner.lifespan < time::OffsetDateTime::now_utc()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SameSite;
+    use base64ct::{Base64, Encoding};
+    use time::{Duration, OffsetDateTime};
+
+    fn token(value: &str, source: TokenSource, config: CsrfConfig) -> CsrfToken {
+        CsrfToken::load(Some(value.to_owned()), source, config, None)
+    }
+
+    fn token_with_mac<M: crate::TokenMac>(config: CsrfConfig) -> CsrfToken {
+        token("value", TokenSource::Cookie, config.with_mac::<M>())
+    }
+
+    #[test]
+    fn authenticity_token_verifies() {
+        let token = CsrfToken::load(None, TokenSource::Fresh, CsrfConfig::default(), None);
+        let authenticity_token = token.authenticity_token();
+
+        assert_eq!(token.verify(&authenticity_token), Ok(()));
+    }
+
+    #[test]
+    fn authenticity_token_depends_on_salt() {
+        let config = CsrfConfig::default().with_salt("salt one");
+        let first = token("value", TokenSource::Cookie, config.clone());
+        let second = token("value", TokenSource::Cookie, config.with_salt("salt two"));
+
+        assert_ne!(first.authenticity_token(), second.authenticity_token());
+    }
+
+    #[test]
+    fn authenticity_token_names_algorithm() {
+        let config = CsrfConfig::default();
+        let token = token("value", TokenSource::Cookie, config.clone());
+        let authenticity_token = token.authenticity_token();
+        let (id, encoded) = authenticity_token.split_once('.').unwrap();
+
+        assert_eq!(id, "hs256");
+        assert_eq!(token.verify(encoded), Ok(()));
+
+        let other = token_with_mac::<crate::HmacSha512>(config);
+        assert_eq!(other.verify(&authenticity_token), Err(CsrfError::Algorithm));
+    }
+
+    #[test]
+    fn other_algorithms_verify() {
+        let config = CsrfConfig::default();
+        let sha512 = token_with_mac::<crate::HmacSha512>(config.clone());
+        let authenticity_token = sha512.authenticity_token();
+
+        assert!(authenticity_token.starts_with("hs512."));
+        assert_eq!(sha512.verify(&authenticity_token), Ok(()));
+        assert_eq!(
+            sha512.verify(&format!("hs512.{}", Base64::encode_string(&[0; 32]))),
+            Err(CsrfError::TokenLength {
+                expected: 64,
+                found: 32
+            })
+        );
+
+        #[cfg(feature = "blake3")]
+        {
+            let blake3 = token_with_mac::<crate::Blake3>(config);
+            let authenticity_token = blake3.authenticity_token();
+
+            assert!(authenticity_token.starts_with("b3."));
+            assert_eq!(blake3.verify(&authenticity_token), Ok(()));
+            assert_eq!(
+                blake3.verify(&format!("b3.{}", Base64::encode_string(&[0; 32]))),
+                Err(CsrfError::Verify)
+            );
+        }
+    }
+
+    #[test]
+    fn verify_uses_configured_encoding() {
+        use crate::TokenEncoding;
+
+        let config = CsrfConfig::default().with_encoding(TokenEncoding::Hex);
+        let hex = token("value", TokenSource::Cookie, config.clone());
+        let authenticity_token = hex.authenticity_token();
+        let (_, encoded) = authenticity_token.split_once('.').unwrap();
+
+        assert_eq!(encoded.len(), 64);
+        assert!(encoded.bytes().all(|b| b.is_ascii_hexdigit()));
+        assert_eq!(hex.verify(&authenticity_token), Ok(()));
+
+        let base64url = token(
+            "value",
+            TokenSource::Cookie,
+            config.with_encoding(TokenEncoding::Base64Url),
+        );
+        assert_eq!(
+            base64url.verify(&authenticity_token),
+            Err(CsrfError::TokenLength {
+                expected: 32,
+                found: 48
+            })
+        );
+
+        let tolerant = token(
+            "value",
+            TokenSource::Cookie,
+            base64url.config.clone().with_tolerant_decoding(true),
+        );
+        assert_eq!(tolerant.verify(&authenticity_token), Ok(()));
+        assert_eq!(tolerant.verify(&tolerant.authenticity_token()), Ok(()));
+    }
+
+    #[test]
+    fn url_with_token_appends_query() {
+        let token = token("value", TokenSource::Cookie, CsrfConfig::default());
+        let authenticity_token = token.authenticity_token();
+        let encoded: String =
+            form_urlencoded::byte_serialize(authenticity_token.as_bytes()).collect();
+
+        assert_eq!(
+            token.url_with_token("/logout"),
+            format!("/logout?authenticity_token={encoded}")
+        );
+        assert_eq!(
+            token.url_with_token("/logout?next=/#top"),
+            format!("/logout?next=/&authenticity_token={encoded}#top")
+        );
+        assert_eq!(
+            token.url_with_token("/logout?"),
+            format!("/logout?authenticity_token={encoded}")
+        );
+    }
+
+    #[test]
+    fn verify_query_reads_token() {
+        let token = token("value", TokenSource::Cookie, CsrfConfig::default());
+        let uri: http::Uri = token.url_with_token("/logout?next=/").parse().unwrap();
+
+        assert_eq!(token.verify_query(&uri), Ok(()));
+        assert_eq!(
+            token.verify_query(&"/logout?next=/".parse().unwrap()),
+            Err(CsrfError::MissingToken)
+        );
+    }
+
+    #[test]
+    fn verify_rejects_malformed_base64() {
+        let token = token("value", TokenSource::Cookie, CsrfConfig::default());
+
+        assert_eq!(token.verify("not base64!"), Err(CsrfError::MalformedToken));
+        assert_eq!(
+            token.verify(""),
+            Err(CsrfError::TokenLength {
+                expected: 32,
+                found: 0
+            })
+        );
+    }
+
+    #[test]
+    fn verify_rejects_wrong_length() {
+        let token = token("value", TokenSource::Cookie, CsrfConfig::default());
+
+        assert_eq!(
+            token.verify(&Base64::encode_string(&[0; 16])),
+            Err(CsrfError::TokenLength {
+                expected: 32,
+                found: 16
+            })
+        );
+        assert_eq!(
+            token.verify(&Base64::encode_string(&[0; 48])),
+            Err(CsrfError::TokenLength {
+                expected: 32,
+                found: 48
+            })
+        );
+        assert_eq!(
+            token.verify(&Base64::encode_string(&[0; 34])),
+            Err(CsrfError::TokenLength {
+                expected: 32,
+                found: 34
+            })
+        );
+    }
+
+    #[test]
+    fn verify_rejects_other_token() {
+        let config = CsrfConfig::default();
+        let token = token("value", TokenSource::Cookie, config.clone());
+        let other = CsrfToken::load(None, TokenSource::Fresh, config, None);
+
+        assert_eq!(
+            token.verify(&other.authenticity_token()),
+            Err(CsrfError::Verify)
+        );
+    }
+
+    #[test]
+    fn verify_reports_missing_and_invalid_cookies() {
+        let config = CsrfConfig::default();
+        let form = token("value", TokenSource::Cookie, config.clone()).authenticity_token();
+
+        let fresh = CsrfToken::load(None, TokenSource::Fresh, config.clone(), None);
+        let invalid = CsrfToken::load(None, TokenSource::CookieInvalid, config.clone(), None);
+        let duplicate = CsrfToken::load(None, TokenSource::CookieDuplicate, config, None);
+
+        assert_eq!(fresh.verify(&form), Err(CsrfError::CookieMissing));
+        assert_eq!(invalid.verify(&form), Err(CsrfError::CookieInvalid));
+        assert_eq!(duplicate.verify(&form), Err(CsrfError::DuplicateCookie));
+    }
+
+    #[test]
+    fn source_tells_if_token_is_new() {
+        let config = CsrfConfig::default();
+
+        assert!(!token("value", TokenSource::Cookie, config.clone())
+            .source()
+            .is_new());
+        assert!(
+            CsrfToken::load(None, TokenSource::CookieInvalid, config, None)
+                .source()
+                .is_new()
+        );
+        assert!(TokenSource::Fresh.is_new());
+        assert!(TokenSource::CookieDuplicate.is_new());
+    }
+
+    #[test]
+    fn expired_cookie_is_replaced() {
+        let config = CsrfConfig::default().with_lifetime(Duration::hours(1));
+        let issued_at = (OffsetDateTime::now_utc() - Duration::hours(2)).unix_timestamp();
+        let value = format!("value.{issued_at}");
+
+        let unlimited = token(
+            &value,
+            TokenSource::Cookie,
+            config.clone().with_lifetime(Duration::ZERO),
+        );
+        let authenticity_token = unlimited.authenticity_token();
+
+        assert_eq!(unlimited.source(), TokenSource::Cookie);
+        assert_eq!(unlimited.verify(&authenticity_token), Ok(()));
+
+        let expired = token(&value, TokenSource::Cookie, config);
+
+        assert_eq!(expired.source(), TokenSource::CookieExpired);
+        assert!(expired.source().is_new());
+        assert_ne!(expired.token, value);
+        assert_eq!(expired.verify(&authenticity_token), Err(CsrfError::Expired));
+        assert_eq!(expired.verify(&expired.authenticity_token()), Ok(()));
+    }
+
+    #[test]
+    fn issued_at_reads_timestamp() {
+        let config = CsrfConfig::default().with_lifetime(Duration::ZERO);
+
+        assert_eq!(
+            token("value.1700000000", TokenSource::Cookie, config.clone()).issued_at(),
+            OffsetDateTime::from_unix_timestamp(1_700_000_000).ok()
+        );
+        assert_eq!(
+            token("legacy", TokenSource::Cookie, config).issued_at(),
+            None
+        );
+    }
+
+    #[test]
+    fn build_cookie_uses_config() {
+        let config = CsrfConfig::default()
+            .with_prefix_with_host(true)
+            .with_cookie_path("/app")
+            .with_cookie_domain(Some("example.com"))
+            .with_secure(true)
+            .with_http_only(false)
+            .with_cookie_same_site(SameSite::Strict);
+        let cookie = token("value", TokenSource::Cookie, config).build_cookie();
+
+        assert_eq!(cookie.name(), "__Host-Csrf_Token");
+        assert_eq!(cookie.value(), "value");
+        assert_eq!(cookie.path(), Some("/app"));
+        assert_eq!(cookie.domain(), Some("example.com"));
+        assert_eq!(cookie.secure(), Some(true));
+        assert_eq!(cookie.http_only(), Some(false));
+        assert_eq!(cookie.same_site(), Some(SameSite::Strict));
+        assert!(cookie.expires().is_some());
+    }
+
+    #[test]
+    fn build_cookie_skips_expires_without_lifespan() {
+        let config = CsrfConfig::default().with_lifetime(Duration::ZERO);
+        let cookie = token("value", TokenSource::Cookie, config).build_cookie();
+
+        assert!(cookie.expires().is_none());
+    }
+
+    #[test]
+    fn into_response_sets_encrypted_cookie() {
+        let response = token("secret", TokenSource::Cookie, CsrfConfig::default()).into_response();
+        let set_cookie = response.headers()[http::header::SET_COOKIE]
+            .to_str()
+            .unwrap();
+
+        assert!(set_cookie.starts_with("Csrf_Token="));
+        assert!(!set_cookie.contains("secret"));
+    }
+
+    #[test]
+    fn hidden_input_uses_field_name() {
+        let config = CsrfConfig::default().with_field_name("csrf\"field");
+        let token = token("value", TokenSource::Cookie, config);
+        let authenticity_token = token.authenticity_token();
+
+        assert_eq!(
+            token.hidden_input().to_string(),
+            format!(
+                r#"<input type="hidden" name="csrf&quot;field" value="{authenticity_token}"/>"#
+            )
+        );
+        assert_eq!(
+            token.meta_tag().to_string(),
+            format!(
+                r#"<meta name="csrf-param" content="csrf&quot;field"/><meta name="csrf-token" content="{authenticity_token}"/>"#
+            )
+        );
+    }
 }
@@ -0,0 +1,14 @@
+use http::{header::ORIGIN, HeaderMap, Method, Uri};
+use tracing::Span;
+
+/// Span covering the CSRF work done for a request.
+///
+/// Only the method, path and Origin header are recorded so no cookie or token values end up in logs.
+pub(crate) fn request_span(method: &Method, uri: &Uri, headers: &HeaderMap) -> Span {
+    tracing::debug_span!(
+        "csrf",
+        method = %method,
+        route = uri.path(),
+        origin = headers.get(ORIGIN).and_then(|origin| origin.to_str().ok()),
+    )
+}
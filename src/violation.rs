@@ -0,0 +1,129 @@
+use crate::{CsrfError, CsrfToken, TokenSource};
+use async_trait::async_trait;
+use axum_core::response::Response;
+use http::{
+    header::{ORIGIN, REFERER, USER_AGENT},
+    request::Parts,
+    HeaderMap, HeaderName, Method,
+};
+use std::fmt;
+
+/// Longest header value kept within a [`CsrfViolation`].
+const MAX_VALUE_LEN: usize = 256;
+
+/// Details of a failed CSRF check passed to a [`CsrfViolationHandler`].
+///
+/// Values are sanitized so no cookies, tokens or query strings are included. The request's Token is
+/// only reachable through [`CsrfViolation::token`] so a response can render a new form, and is left out of
+/// the `Debug` output.
+#[derive(Clone)]
+pub struct CsrfViolation {
+    /// Request method.
+    pub method: Method,
+    /// Request path without the query string.
+    pub path: String,
+    /// Origin header.
+    pub origin: Option<String>,
+    /// Referer header without its query string or fragment.
+    pub referer: Option<String>,
+    /// User-Agent header.
+    pub user_agent: Option<String>,
+    /// Client IP read from the layer's configured client IP header.
+    pub client_ip: Option<String>,
+    /// Why the check failed.
+    pub reason: CsrfError,
+    /// Where the request's Token came from, a new one means the cookie was missing or rejected.
+    pub source: TokenSource,
+    token: CsrfToken,
+}
+
+impl CsrfViolation {
+    pub(crate) fn new(
+        parts: &Parts,
+        reason: CsrfError,
+        token: &CsrfToken,
+        client_ip_header: Option<&HeaderName>,
+    ) -> Self {
+        let referer = header(&parts.headers, &REFERER).map(|referer| {
+            let end = referer.find(['?', '#']).unwrap_or(referer.len());
+            referer[..end].to_owned()
+        });
+
+        let client_ip = client_ip_header
+            .and_then(|name| header(&parts.headers, name))
+            .and_then(|value| value.split(',').next())
+            .map(|ip| ip.trim().to_owned());
+
+        Self {
+            method: parts.method.clone(),
+            path: parts.uri.path().to_owned(),
+            origin: header(&parts.headers, &ORIGIN).map(str::to_owned),
+            referer,
+            user_agent: header(&parts.headers, &USER_AGENT).map(str::to_owned),
+            client_ip,
+            reason,
+            source: token.source(),
+            token: token.clone(),
+        }
+    }
+
+    /// The request's Token, a new one when [`CsrfViolation::source`] says so.
+    ///
+    /// Use it to render a form with a fresh authenticity token within an overriding response.
+    /// The layer adds the Token's cookie to that response unless it already sets one.
+    pub fn token(&self) -> &CsrfToken {
+        &self.token
+    }
+}
+
+impl fmt::Debug for CsrfViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CsrfViolation")
+            .field("method", &self.method)
+            .field("path", &self.path)
+            .field("origin", &self.origin)
+            .field("referer", &self.referer)
+            .field("user_agent", &self.user_agent)
+            .field("client_ip", &self.client_ip)
+            .field("reason", &self.reason)
+            .field("source", &self.source)
+            .finish_non_exhaustive()
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &HeaderName) -> Option<&'a str> {
+    let value = headers.get(name)?.to_str().ok()?;
+    let end = value
+        .char_indices()
+        .nth(MAX_VALUE_LEN)
+        .map_or(value.len(), |(i, _)| i);
+
+    Some(&value[..end])
+}
+
+/// Handler called by the layer for every failed CSRF check.
+///
+/// Use it to forward violations to a SIEM or audit log. When the request gets rejected with
+/// [`crate::CsrfEnforcement::Enforce`] the returned response replaces the default `403 Forbidden`,
+/// and gets the Token's cookie added so a form rendered with [`CsrfViolation::token`] can be submitted.
+///
+/// # Examples
+/// ```rust
+/// use axum_csrf::{CsrfViolation, CsrfViolationHandler};
+/// use axum_core::response::Response;
+///
+/// struct Audit;
+///
+/// #[axum_csrf::async_trait]
+/// impl CsrfViolationHandler for Audit {
+///     async fn on_violation(&self, violation: &CsrfViolation) -> Option<Response> {
+///         eprintln!("CSRF violation {} {}: {}", violation.method, violation.path, violation.reason);
+///         None
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait CsrfViolationHandler: Send + Sync + 'static {
+    /// Called with the violation's details. Return a response to override the rejection.
+    async fn on_violation(&self, violation: &CsrfViolation) -> Option<Response>;
+}
@@ -0,0 +1,859 @@
+#![cfg(feature = "layer")]
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    response::{Html, IntoResponse, Response},
+    routing::get,
+    Extension, Router,
+};
+use axum_csrf::{
+    policy::{FetchMetadata, Origin, Token},
+    ContentTypePolicy, CsrfConfig, CsrfEnforcement, CsrfLayer, CsrfOutcome, CsrfPolicy, CsrfToken,
+    CsrfViolation, CsrfViolationHandler, HeaderCheckMode, RequiredHeader,
+};
+use http_body_util::BodyExt;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use tower::ServiceExt;
+
+const PAGE: &str =
+    r#"<html><form method="post" action="/"></form><form method="get"></form></html>"#;
+
+fn app(layer: CsrfLayer) -> Router {
+    Router::new()
+        .route(
+            "/",
+            get(|token: CsrfToken| async move {
+                let input = token.hidden_input().to_string();
+                (token, Html(input))
+            })
+            .post(|outcome: Option<Extension<CsrfOutcome>>| async move {
+                format!("{:?}", outcome.map(|outcome| outcome.0))
+            }),
+        )
+        .route("/legacy", get(|| async { Html(PAGE) }))
+        .route("/logout", get(|| async { "logged out" }))
+        .layer(layer)
+}
+
+async fn body_string(response: Response) -> String {
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+fn set_cookie(response: &Response) -> String {
+    response.headers()[header::SET_COOKIE]
+        .to_str()
+        .unwrap()
+        .split(';')
+        .next()
+        .unwrap()
+        .to_owned()
+}
+
+fn form_value(body: &str) -> String {
+    body.split("value=\"")
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap()
+        .to_owned()
+}
+
+async fn load_form(app: &Router) -> (String, String) {
+    let response = app
+        .clone()
+        .oneshot(Request::get("/").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let cookie = set_cookie(&response);
+
+    (cookie, form_value(&body_string(response).await))
+}
+
+fn form_post(cookie: &str, token: &str) -> Request<Body> {
+    let body = form_urlencoded::Serializer::new(String::new())
+        .append_pair("name", "value")
+        .append_pair("authenticity_token", token)
+        .finish();
+
+    Request::post("/")
+        .header(header::COOKIE, cookie)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .header(header::CONTENT_LENGTH, body.len())
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn layer_provides_token_without_enforcement() {
+    let app = app(CsrfLayer::new(CsrfConfig::default()));
+    let (cookie, _) = load_form(&app).await;
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::post("/")
+                .header(header::COOKIE, cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body_string(response).await, "None");
+}
+
+#[tokio::test]
+async fn enforce_accepts_form_token() {
+    let app = app(CsrfLayer::new(CsrfConfig::default()).with_enforcement(CsrfEnforcement::Enforce));
+    let (cookie, token) = load_form(&app).await;
+
+    let response = app
+        .clone()
+        .oneshot(form_post(&cookie, &token))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body_string(response).await, "Some(Passed)");
+}
+
+#[tokio::test]
+async fn enforce_accepts_header_token() {
+    let app = app(CsrfLayer::new(CsrfConfig::default())
+        .with_enforcement(CsrfEnforcement::Enforce)
+        .with_header_name("x-xsrf-token"));
+    let (cookie, token) = load_form(&app).await;
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::post("/")
+                .header(header::COOKIE, cookie)
+                .header("x-xsrf-token", token)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn enforce_rejects_missing_token() {
+    let app = app(CsrfLayer::new(CsrfConfig::default()).with_enforcement(CsrfEnforcement::Enforce));
+    let (cookie, _) = load_form(&app).await;
+
+    let response = app.clone().oneshot(form_post(&cookie, "")).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn body_longer_than_its_length_is_not_forwarded() {
+    let app = app(CsrfLayer::new(CsrfConfig::default())
+        .with_enforcement(CsrfEnforcement::ReportOnly)
+        .with_body_limit(16));
+    let (cookie, token) = load_form(&app).await;
+
+    let mut request = form_post(&cookie, &token);
+    request
+        .headers_mut()
+        .insert(header::CONTENT_LENGTH, header::HeaderValue::from(8));
+    let response = app.clone().oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn enforce_skips_safe_methods() {
+    let app = app(CsrfLayer::new(CsrfConfig::default()).with_enforcement(CsrfEnforcement::Enforce));
+    let response = app
+        .oneshot(Request::get("/").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn report_only_forwards_failures() {
+    let reports = Arc::new(AtomicUsize::new(0));
+    let counter = reports.clone();
+    let app = app(CsrfLayer::new(CsrfConfig::default())
+        .with_enforcement(CsrfEnforcement::ReportOnly)
+        .with_on_failure(move |_, _| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+    let (_, token) = load_form(&app).await;
+
+    let response = app.clone().oneshot(form_post("", &token)).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body_string(response).await, "Some(Failed(CookieMissing))");
+    assert_eq!(reports.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn query_token_routes_check_get_requests() {
+    let app = app(CsrfLayer::new(CsrfConfig::default())
+        .with_enforcement(CsrfEnforcement::Enforce)
+        .with_query_token_routes(["/logout"]));
+    let (cookie, token) = load_form(&app).await;
+    let query = form_urlencoded::Serializer::new(String::new())
+        .append_pair("authenticity_token", &token)
+        .finish();
+
+    let logout = |uri: String| {
+        Request::get(uri)
+            .header(header::COOKIE, &cookie)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = app
+        .clone()
+        .oneshot(logout(format!("/logout?{query}")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app.clone().oneshot(logout("/logout".into())).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = app.clone().oneshot(logout("/".into())).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+struct Teapot;
+
+#[axum_csrf::async_trait]
+impl CsrfViolationHandler for Teapot {
+    async fn on_violation(&self, violation: &CsrfViolation) -> Option<Response> {
+        Some((StatusCode::IM_A_TEAPOT, violation.reason.code()).into_response())
+    }
+}
+
+#[tokio::test]
+async fn violation_handler_overrides_rejection() {
+    let app = app(CsrfLayer::new(CsrfConfig::default())
+        .with_enforcement(CsrfEnforcement::Enforce)
+        .with_violation_handler(Teapot));
+    let (cookie, _) = load_form(&app).await;
+
+    let response = app
+        .clone()
+        .oneshot(form_post(&cookie, "bad token"))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+    assert_eq!(body_string(response).await, "malformed_token");
+}
+
+struct RenderAgain;
+
+#[axum_csrf::async_trait]
+impl CsrfViolationHandler for RenderAgain {
+    async fn on_violation(&self, violation: &CsrfViolation) -> Option<Response> {
+        violation.source.is_new().then(|| {
+            let input = violation.token().hidden_input();
+            Html(format!("{:?} {input}", violation.source)).into_response()
+        })
+    }
+}
+
+#[tokio::test]
+async fn violation_handler_renders_new_token() {
+    let app = app(CsrfLayer::new(CsrfConfig::default())
+        .with_enforcement(CsrfEnforcement::Enforce)
+        .with_violation_handler(RenderAgain));
+    let (cookie, token) = load_form(&app).await;
+
+    let response = app
+        .clone()
+        .oneshot(form_post("Csrf_Token=tampered", &token))
+        .await
+        .unwrap();
+
+    // The rendered form comes with the new Token's cookie, so it can be submitted.
+    assert_eq!(response.status(), StatusCode::OK);
+    let new_cookie = set_cookie(&response);
+    let body = body_string(response).await;
+    assert!(body.starts_with("CookieInvalid "));
+
+    let response = app
+        .clone()
+        .oneshot(form_post(&new_cookie, &form_value(&body)))
+        .await
+        .unwrap();
+    assert_eq!(body_string(response).await, "Some(Passed)");
+
+    let response = app
+        .clone()
+        .oneshot(form_post(&cookie, "bad token"))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn form_injection_adds_input_and_cookie() {
+    let app = app(CsrfLayer::new(CsrfConfig::default()).with_form_injection(true));
+    let response = app
+        .clone()
+        .oneshot(Request::get("/legacy").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert!(response.headers().get(header::CONTENT_LENGTH).is_none());
+    let cookie = set_cookie(&response);
+    let body = body_string(response).await;
+
+    assert_eq!(body.matches("<input").count(), 1);
+    assert!(body.starts_with(r#"<html><form method="post" action="/"><input type="hidden""#));
+
+    let response = app
+        .oneshot(form_post(&cookie, &form_value(&body)))
+        .await
+        .unwrap();
+
+    assert_eq!(body_string(response).await, "None");
+}
+
+#[tokio::test]
+async fn content_type_policy_replaces_token_on_its_routes() {
+    let policy = ContentTypePolicy::new(["application/json"])
+        .with_routes(["/"])
+        .with_required_header(RequiredHeader::new("x-requested-with"));
+    let app = app(CsrfLayer::new(CsrfConfig::default())
+        .with_enforcement(CsrfEnforcement::Enforce)
+        .with_content_type_policy(policy));
+    let (cookie, token) = load_form(&app).await;
+
+    let json_post = |requested_with: bool| {
+        let request =
+            Request::post("/").header(header::CONTENT_TYPE, "Application/JSON; charset=utf-8");
+        let request = if requested_with {
+            request.header("x-requested-with", "fetch")
+        } else {
+            request
+        };
+
+        request.body(Body::from("{}")).unwrap()
+    };
+
+    let response = app.clone().oneshot(json_post(true)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body_string(response).await, "Some(Passed)");
+
+    let response = app.clone().oneshot(json_post(false)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // Forms can be sent cross-site, so even one with a valid token is refused on these routes.
+    let response = app
+        .clone()
+        .oneshot(form_post(&cookie, &token))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+fn header_post(value: &str) -> Request<Body> {
+    Request::post("/")
+        .header("x-requested-with", value)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn required_header_any_passes_without_token() {
+    let header = RequiredHeader::new("x-requested-with").with_value("XMLHttpRequest");
+    let app = app(CsrfLayer::new(CsrfConfig::default())
+        .with_enforcement(CsrfEnforcement::Enforce)
+        .with_required_header(header, HeaderCheckMode::Any));
+    let (cookie, token) = load_form(&app).await;
+
+    let response = app
+        .clone()
+        .oneshot(header_post("xmlhttprequest"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app.clone().oneshot(header_post("fetch")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = app
+        .clone()
+        .oneshot(form_post(&cookie, &token))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn required_header_all_needs_header_and_token() {
+    let app = app(CsrfLayer::new(CsrfConfig::default())
+        .with_enforcement(CsrfEnforcement::Enforce)
+        .with_required_header(
+            RequiredHeader::new("x-requested-with"),
+            HeaderCheckMode::All,
+        ));
+    let (cookie, token) = load_form(&app).await;
+
+    let response = app.clone().oneshot(header_post("fetch")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = app
+        .clone()
+        .oneshot(form_post(&cookie, &token))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let mut request = form_post(&cookie, &token);
+    request
+        .headers_mut()
+        .insert("x-requested-with", "fetch".parse().unwrap());
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn policy_combines_fetch_metadata_origin_and_token() {
+    let app = app(CsrfLayer::new(CsrfConfig::default())
+        .with_enforcement(CsrfEnforcement::Enforce)
+        .with_policy(FetchMetadata::new().or(Origin::new().and(Token))));
+    let (cookie, token) = load_form(&app).await;
+
+    let with_headers = |mut request: Request<Body>, headers: &[(&'static str, &'static str)]| {
+        for (name, value) in headers {
+            request.headers_mut().insert(*name, value.parse().unwrap());
+        }
+        request
+    };
+    let empty_post = || Request::post("/").body(Body::empty()).unwrap();
+
+    let response = app
+        .clone()
+        .oneshot(with_headers(
+            empty_post(),
+            &[("sec-fetch-site", "same-origin")],
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .clone()
+        .oneshot(with_headers(
+            empty_post(),
+            &[("sec-fetch-site", "cross-site")],
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // Without fetch metadata the Origin must match and the token be valid.
+    let same_origin = [("host", "example.com"), ("origin", "https://example.com")];
+
+    let response = app
+        .clone()
+        .oneshot(with_headers(form_post(&cookie, &token), &same_origin))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .clone()
+        .oneshot(with_headers(form_post(&cookie, ""), &same_origin))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = app
+        .clone()
+        .oneshot(with_headers(
+            form_post(&cookie, &token),
+            &[
+                ("host", "example.com"),
+                ("referer", "https://evil.example/form"),
+            ],
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // The scheme is compared too, an http page cannot post to an https site.
+    let response = app
+        .clone()
+        .oneshot(with_headers(
+            form_post(&cookie, &token),
+            &[("host", "example.com"), ("origin", "http://example.com")],
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn policy_without_token_leaves_body_unread() {
+    let app = app(CsrfLayer::new(CsrfConfig::default())
+        .with_enforcement(CsrfEnforcement::Enforce)
+        .with_body_limit(16)
+        .with_policy(FetchMetadata::new()));
+    let (cookie, token) = load_form(&app).await;
+
+    // Reading this body would fail as it is longer than its Content-Length says.
+    let mut request = form_post(&cookie, &token);
+    let headers = request.headers_mut();
+    headers.insert(header::CONTENT_LENGTH, header::HeaderValue::from(8));
+    headers.insert(
+        "sec-fetch-site",
+        header::HeaderValue::from_static("same-origin"),
+    );
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn policy_when_and_not() {
+    let policy = Token
+        .when(|parts| parts.uri.path() != "/logout")
+        .and(RequiredHeader::new("x-forbidden").not());
+    let app = app(CsrfLayer::new(CsrfConfig::default())
+        .with_enforcement(CsrfEnforcement::Enforce)
+        .with_query_token_routes(["/logout"])
+        .with_policy(policy));
+
+    let response = app
+        .clone()
+        .oneshot(Request::get("/logout").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::get("/logout")
+                .header("x-forbidden", "1")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = app
+        .clone()
+        .oneshot(Request::post("/").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn policy_combines_with_layer_checks_set_after_it() {
+    let cleared = app(CsrfLayer::new(CsrfConfig::default())
+        .with_enforcement(CsrfEnforcement::Enforce)
+        .with_required_header(
+            RequiredHeader::new("x-requested-with"),
+            HeaderCheckMode::Any,
+        )
+        .with_policy(FetchMetadata::new()));
+    let combined = app(CsrfLayer::new(CsrfConfig::default())
+        .with_enforcement(CsrfEnforcement::Enforce)
+        .with_policy(FetchMetadata::new())
+        .with_required_header(
+            RequiredHeader::new("x-requested-with"),
+            HeaderCheckMode::Any,
+        ));
+    let post = |headers: &[(&'static str, &'static str)]| {
+        let mut request = Request::post("/").body(Body::empty()).unwrap();
+        for (name, value) in headers {
+            request.headers_mut().insert(*name, value.parse().unwrap());
+        }
+        request
+    };
+
+    // Setting the policy cleared the header set before it.
+    let response = cleared
+        .clone()
+        .oneshot(post(&[("x-requested-with", "fetch")]))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // The header set after the policy passes on its own, like it does instead of the Token.
+    let response = combined
+        .clone()
+        .oneshot(post(&[("x-requested-with", "fetch")]))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = combined
+        .clone()
+        .oneshot(post(&[("sec-fetch-site", "same-origin")]))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = combined.clone().oneshot(post(&[])).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn content_type_policy_set_after_policy_checks_its_routes() {
+    let app = app(CsrfLayer::new(CsrfConfig::default())
+        .with_enforcement(CsrfEnforcement::Enforce)
+        .with_policy(FetchMetadata::new())
+        .with_content_type_policy(
+            ContentTypePolicy::new(["application/json"]).with_routes(["/api/"]),
+        ));
+
+    // Reaching the router, which has no such route, means the layer let it through.
+    let response = app
+        .clone()
+        .oneshot(
+            Request::post("/api/comments")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Other routes are still checked by the policy.
+    let response = app
+        .clone()
+        .oneshot(
+            Request::post("/")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[cfg(feature = "json")]
+mod json {
+    use super::*;
+    use axum::{routing::post, Json};
+
+    #[derive(serde::Deserialize)]
+    struct Comment {
+        text: String,
+    }
+
+    fn json_app(layer: CsrfLayer) -> Router {
+        Router::new()
+            .route(
+                "/",
+                get(|token: CsrfToken| async move {
+                    let input = token.hidden_input().to_string();
+                    (token, Html(input))
+                }),
+            )
+            .route(
+                "/comments",
+                post(|Json(comment): Json<Comment>| async move { comment.text }),
+            )
+            .layer(layer)
+    }
+
+    fn json_post(cookie: &str, body: String) -> Request<Body> {
+        Request::post("/comments")
+            .header(header::COOKIE, cookie)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn enforce_accepts_json_token_and_keeps_body() {
+        let layer = CsrfLayer::new(CsrfConfig::default())
+            .with_enforcement(CsrfEnforcement::Enforce)
+            .with_json_field("csrf");
+        let app = json_app(layer);
+        let (cookie, token) = load_form(&app).await;
+
+        let response = app
+            .clone()
+            .oneshot(json_post(
+                &cookie,
+                format!(r#"{{"text": "hello", "csrf": "{token}"}}"#),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_string(response).await, "hello");
+
+        let response = app
+            .clone()
+            .oneshot(json_post(
+                &cookie,
+                format!(r#"{{"text": "hello", "authenticity_token": "{token}"}}"#),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn enforce_skips_json_over_body_limit() {
+        let layer = CsrfLayer::new(CsrfConfig::default())
+            .with_enforcement(CsrfEnforcement::Enforce)
+            .with_body_limit(16);
+        let app = json_app(layer);
+        let (cookie, token) = load_form(&app).await;
+
+        let response = app
+            .clone()
+            .oneshot(json_post(
+                &cookie,
+                format!(r#"{{"text": "hello", "authenticity_token": "{token}"}}"#),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+}
+
+#[cfg(all(feature = "metrics", feature = "test-util"))]
+mod metrics {
+    use super::*;
+    use axum_csrf::test_util::TestToken;
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+    /// Runs the requests with a local recorder, returning the failed counters by reason.
+    fn failed_counters(config: CsrfConfig, requests: Vec<Request<Body>>) -> Vec<(String, u64)> {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        ::metrics::with_local_recorder(&recorder, || {
+            runtime.block_on(async {
+                let app = app(CsrfLayer::new(config).with_enforcement(CsrfEnforcement::ReportOnly));
+
+                for request in requests {
+                    app.clone().oneshot(request).await.unwrap();
+                }
+            })
+        });
+
+        let mut counters: Vec<_> = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .filter(|(key, ..)| key.key().name() == "axum_csrf_verifications_failed_total")
+            .map(|(key, _, _, value)| {
+                let reason = key
+                    .key()
+                    .labels()
+                    .find(|label| label.key() == "reason")
+                    .unwrap()
+                    .value()
+                    .to_owned();
+                let DebugValue::Counter(count) = value else {
+                    panic!("not a counter");
+                };
+                (reason, count)
+            })
+            .collect();
+
+        counters.sort();
+        counters
+    }
+
+    #[test]
+    fn layer_failures_are_counted_once() {
+        let config = CsrfConfig::default();
+        let token = TestToken::new(config.clone());
+        let cookie = token.cookie_header().to_str().unwrap();
+
+        let counters = failed_counters(
+            config,
+            vec![
+                // A cross-site form post without any token.
+                Request::post("/")
+                    .header(header::COOKIE, cookie)
+                    .body(Body::empty())
+                    .unwrap(),
+                form_post(cookie, "hs256.AAAA"),
+            ],
+        );
+
+        assert_eq!(
+            counters,
+            [
+                ("missing_token".to_owned(), 1),
+                ("token_length".to_owned(), 1)
+            ]
+        );
+    }
+}
+
+#[cfg(feature = "tower-sessions")]
+mod session {
+    use super::*;
+    use axum_csrf::SessionCsrfToken;
+    use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+    fn session_app(layer: CsrfLayer) -> Router {
+        Router::new()
+            .route(
+                "/",
+                get(|SessionCsrfToken(token): SessionCsrfToken| async move {
+                    Html(token.hidden_input().to_string())
+                })
+                .post(|| async { "posted" }),
+            )
+            .layer(layer)
+            .layer(SessionManagerLayer::new(MemoryStore::default()).with_secure(false))
+    }
+
+    #[tokio::test]
+    async fn enforce_accepts_session_token() {
+        let layer =
+            CsrfLayer::new(CsrfConfig::default()).with_enforcement(CsrfEnforcement::Enforce);
+        let app = session_app(layer);
+        let (session_cookie, token) = load_form(&app).await;
+
+        let response = app
+            .clone()
+            .oneshot(form_post(&session_cookie, &token))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_string(response).await, "posted");
+
+        let response = app
+            .clone()
+            .oneshot(form_post(&session_cookie, "hs256.AAAA"))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,158 @@
+#![cfg(not(feature = "layer"))]
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    response::{Html, IntoResponse},
+    routing::get,
+    Form, Router,
+};
+use axum_csrf::{CsrfConfig, CsrfToken};
+use http_body_util::BodyExt;
+use serde::Deserialize;
+use tower::ServiceExt;
+
+#[derive(Deserialize)]
+struct Keys {
+    authenticity_token: String,
+}
+
+fn app(config: CsrfConfig) -> Router {
+    Router::new()
+        .route("/", get(root).post(check_key))
+        .with_state(config)
+}
+
+async fn root(token: CsrfToken) -> impl IntoResponse {
+    let input = token.hidden_input().to_string();
+    (token, Html(input))
+}
+
+async fn check_key(token: CsrfToken, Form(payload): Form<Keys>) -> StatusCode {
+    match token.verify(&payload.authenticity_token) {
+        Ok(()) => StatusCode::OK,
+        Err(_) => StatusCode::FORBIDDEN,
+    }
+}
+
+/// Loads the page returning the cookie to send back and the authenticity token within the form.
+async fn load_form(app: &Router, cookie: Option<&str>) -> (String, String) {
+    let mut request = Request::get("/");
+
+    if let Some(cookie) = cookie {
+        request = request.header(header::COOKIE, cookie);
+    }
+
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let cookie = response.headers()[header::SET_COOKIE]
+        .to_str()
+        .unwrap()
+        .split(';')
+        .next()
+        .unwrap()
+        .to_owned();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    let token = body
+        .split("value=\"")
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap()
+        .to_owned();
+
+    (cookie, token)
+}
+
+async fn post_form(app: &Router, cookie: Option<&str>, token: &str) -> StatusCode {
+    let body = form_urlencoded::Serializer::new(String::new())
+        .append_pair("authenticity_token", token)
+        .finish();
+    let mut request =
+        Request::post("/").header(header::CONTENT_TYPE, "application/x-www-form-urlencoded");
+
+    if let Some(cookie) = cookie {
+        request = request.header(header::COOKIE, cookie);
+    }
+
+    app.clone()
+        .oneshot(request.body(Body::from(body)).unwrap())
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn form_round_trip_verifies() {
+    let app = app(CsrfConfig::default());
+    let (cookie, token) = load_form(&app, None).await;
+
+    assert_eq!(post_form(&app, Some(&cookie), &token).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn cookie_is_reused_across_requests() {
+    let app = app(CsrfConfig::default());
+    let (cookie, token) = load_form(&app, None).await;
+    let (_, second_token) = load_form(&app, Some(&cookie)).await;
+
+    assert_eq!(token, second_token);
+}
+
+#[tokio::test]
+async fn post_without_cookie_fails() {
+    let app = app(CsrfConfig::default());
+    let (_, token) = load_form(&app, None).await;
+
+    assert_eq!(post_form(&app, None, &token).await, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn post_with_other_token_fails() {
+    let app = app(CsrfConfig::default());
+    let (cookie, _) = load_form(&app, None).await;
+    let (_, other_token) = load_form(&app, None).await;
+
+    assert_eq!(
+        post_form(&app, Some(&cookie), &other_token).await,
+        StatusCode::FORBIDDEN
+    );
+}
+
+#[tokio::test]
+async fn host_prefixed_cookie_round_trip() {
+    let app = app(CsrfConfig::default().with_prefix_with_host(true));
+    let (cookie, token) = load_form(&app, None).await;
+
+    assert!(cookie.starts_with("__Host-Csrf_Token="));
+    assert_eq!(post_form(&app, Some(&cookie), &token).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn cookie_from_other_key_fails() {
+    let (cookie, token) = load_form(&app(CsrfConfig::default()), None).await;
+    let other_app = app(CsrfConfig::default());
+
+    assert_eq!(
+        post_form(&other_app, Some(&cookie), &token).await,
+        StatusCode::FORBIDDEN
+    );
+}
+
+#[tokio::test]
+async fn expired_cookie_is_replaced() {
+    let config = CsrfConfig::default()
+        .with_key(None)
+        .with_lifetime(time::Duration::hours(1));
+    let app = app(config);
+    let issued_at = (time::OffsetDateTime::now_utc() - time::Duration::hours(2)).unix_timestamp();
+    let expired = format!("Csrf_Token=AAAAAAAAAAAAAAAAAAAAAA.{issued_at}");
+
+    let (cookie, token) = load_form(&app, Some(&expired)).await;
+
+    assert_ne!(cookie, expired);
+    assert_eq!(post_form(&app, Some(&cookie), &token).await, StatusCode::OK);
+}